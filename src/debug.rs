@@ -1,7 +1,7 @@
 // Debugging module, only used for features that should not get compiled into the final game
 use bevy::{prelude::*, app::AppExit};
 
-use crate::{goat::GoatMovement, helper::{cart_to_iso, GameState}, setup::REVERT};
+use crate::{goat::{spawn_goat, Breed}, helper::GameState, level::CurrentLevel};
 
 // use bevy_editor_pls::EditorPlugin;
 
@@ -70,6 +70,7 @@ fn switch_states(
 			(KeyCode::Digit2, GameState::Menu),
 			(KeyCode::Digit3, GameState::Cutscene),
 			(KeyCode::Digit4, GameState::Game),
+			(KeyCode::Digit5, GameState::Editor),
 		];
 		for (key, state) in key_states.iter() {
 			if keyboard.just_pressed(*key) {
@@ -83,31 +84,11 @@ fn win_level(
 	keyboard: Res<ButtonInput<KeyCode>>,
 	mut commands: Commands,
 	asset_server: Res<AssetServer>,
+	level: Res<CurrentLevel>,
 ) {
 	if keyboard.pressed(KeyCode::KeyO) {
-		let iso_loc = cart_to_iso(Vec2::new(750.0, -50.0)*REVERT);
-		commands.spawn((SpriteBundle {
-			transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 600.0),
-			texture: asset_server.load("goat.png"),
-			sprite: Sprite {
-				custom_size: Some(Vec2::new(64.0, 64.0)),
-				..default()
-			},
-			..default()
-			},
-			GoatMovement {
-				cart_transform: Vec2::new(750.0, -50.0)*REVERT,
-				velocity: Vec2::new(1.0, 0.0),
-				speed: 90.0,
-				boost_timer: Timer::from_seconds(3.0, TimerMode::Once),
-				move_timer: Timer::from_seconds(3.0, TimerMode::Once),
-				idle_timer: Timer::from_seconds(1.0, TimerMode::Once),
-				iso_mode: true,
-				grabbed: false,
-				safe: false,
-				furthest_tile: 0,
-			}
-		));
+		let Some(goal) = level.0.goals.first() else {return};
+		spawn_goat(&mut commands, &asset_server, Breed::Plain, goal.shape.center(), Vec2::X);
 	}
 }
 
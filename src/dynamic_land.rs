@@ -0,0 +1,198 @@
+// Dynamic land module, for land tiles that crumble, come and go or drift
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{goat::{GoatMovement, GoatState}, helper::{cart_to_iso, iso_to_cart, GameState}, land::{Land, LandSetup, LandShape}, level::CurrentLevel, player::PlayerMovement, terrain::{build_terrain, TerrainTiles, TERRAIN_Z}};
+
+pub struct DynamicLandPlugin;
+
+impl Plugin for DynamicLandPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(OnEnter(GameState::Game), (
+				dynamic_land_setup.after(LandSetup),
+			))
+			.add_systems(Update, (
+				update_dynamic_land,
+				carry_riders,
+				update_dynamic_land_art,
+			).chain().run_if(in_state(GameState::Game)))
+		;
+	}
+}
+
+// Above background art and below goats, so dynamic land shows on any level
+const DYNAMIC_LAND_Z: f32 = 555.0;
+// Cart distance drifting land moves before goats get routed over it again
+const DRIFT_REBUILD: f32 = 40.0;
+
+// Level data for one dynamic land tile
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct DynamicLand{
+	pub shape: LandShape,
+	pub behaviour: LandBehaviour,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub enum LandBehaviour {
+	// Falls away for good once goats have stood on it for this many seconds in total
+	Crumble{after: f32},
+	// Solid for solid_for seconds out of every period, starting offset seconds into the cycle
+	Bridge{period: f32, solid_for: f32, offset: f32},
+	// Slides out to offset from where it starts and back again every period seconds
+	Drift{offset: Vec2, period: f32},
+}
+
+impl DynamicLand {
+	pub fn scaled(
+		&self,
+		scale: f32,
+	) -> DynamicLand {
+		DynamicLand{
+			shape: self.shape.scaled(scale),
+			behaviour: match self.behaviour {
+				LandBehaviour::Drift{offset, period} => LandBehaviour::Drift{offset: offset * scale, period},
+				behaviour => behaviour,
+			},
+		}
+	}
+}
+
+#[derive(Component)]
+pub struct DynamicTile{
+	behaviour: LandBehaviour,
+	origin: LandShape,
+	age: f32,
+	// Seconds goats have stood on a crumbling tile
+	stood: f32,
+	// How far a drifting tile moved this frame
+	step: Vec2,
+	// Centre of a drifting tile when the land graph was last rebuilt
+	graphed: Vec2,
+}
+
+// Terrain drawn for one dynamic tile, follows the Land entity it holds
+#[derive(Component)]
+struct DynamicLandArt(Entity);
+
+// Cart position under the feet of a goat or the player
+fn feet(
+	transform: &Transform,
+) -> Vec2 {
+	iso_to_cart(transform.translation.xy() - Vec2::new(0.0, 32.0))
+}
+
+fn dynamic_land_setup(
+	mut commands: Commands,
+	level: Res<CurrentLevel>,
+	land_query: Query<(Entity, &Land)>,
+	terrain_tiles: Res<TerrainTiles>,
+) {
+	let first_dynamic = level.0.land.len() + level.0.goals.len() + level.0.islands.len();
+	for (entity, land) in land_query.iter() {
+		let Some(dynamic) = land.tile_index.checked_sub(first_dynamic).and_then(|i| level.0.dynamic_land.get(i)) else {continue};
+		commands.entity(entity).insert(DynamicTile{
+			behaviour: dynamic.behaviour,
+			origin: dynamic.shape.clone(),
+			age: 0.0,
+			stood: 0.0,
+			step: Vec2::ZERO,
+			graphed: dynamic.shape.center(),
+		});
+		commands
+			.spawn((SpatialBundle {
+				transform: Transform::from_xyz(0.0, 0.0, DYNAMIC_LAND_Z - TERRAIN_Z),
+				..default()
+				},
+				DynamicLandArt(entity),
+			)).with_children(|parent| {
+				build_terrain(parent, &terrain_tiles, &[&dynamic.shape], &[]);
+			});
+	}
+}
+
+fn update_dynamic_land(
+	time: Res<Time>,
+	mut land_query: Query<(&mut Land, &mut DynamicTile)>,
+	goat_query: Query<&Transform, With<GoatMovement>>,
+) {
+	for (mut land, mut tile) in land_query.iter_mut() {
+		tile.age += time.delta_seconds();
+		tile.step = Vec2::ZERO;
+		match tile.behaviour {
+			LandBehaviour::Crumble{after} => {
+				if land.solid && goat_query.iter().any(|transform| land.shape.contains(feet(transform))) {
+					tile.stood += time.delta_seconds();
+					if tile.stood >= after {
+						land.solid = false;
+					}
+				}
+			},
+			LandBehaviour::Bridge{period, solid_for, offset} => {
+				let solid = (tile.age + offset).rem_euclid(period) < solid_for;
+				// Only touch the component on a switch, so the graph isn't rebuilt every frame
+				if land.solid != solid {
+					land.solid = solid;
+				}
+			},
+			LandBehaviour::Drift{offset, period} => {
+				let shape = tile.origin.translated(offset * (1.0 - (tile.age / period * TAU).cos()) / 2.0);
+				tile.step = shape.center() - land.shape.center();
+				// Collision follows every frame, but the graph is only rebuilt once it has drifted a way
+				if shape.center().distance(tile.graphed) > DRIFT_REBUILD {
+					tile.graphed = shape.center();
+					land.shape = shape;
+				} else {
+					land.bypass_change_detection().shape = shape;
+				}
+			},
+		}
+	}
+}
+
+// Goats and the player standing on drifting land move along with it
+fn carry_riders(
+	land_query: Query<(&Land, &DynamicTile)>,
+	mut goat_query: Query<(&mut Transform, &mut GoatMovement), Without<PlayerMovement>>,
+	mut player_query: Query<&mut Transform, (With<PlayerMovement>, Without<GoatMovement>)>,
+) {
+	for (land, tile) in land_query.iter() {
+		if tile.step == Vec2::ZERO || !land.solid {
+			continue;
+		}
+		let before = land.shape.translated(-tile.step);
+		let iso_step = cart_to_iso(tile.step).extend(0.0);
+		for (mut transform, mut goat_movement) in goat_query.iter_mut() {
+			if goat_movement.state() != GoatState::Grabbed && before.contains(feet(&transform)) {
+				transform.translation += iso_step;
+				goat_movement.cart_transform += tile.step;
+			}
+		}
+		for mut transform in player_query.iter_mut() {
+			if before.contains(feet(&transform)) {
+				transform.translation += iso_step;
+			}
+		}
+	}
+}
+
+fn update_dynamic_land_art(
+	land_query: Query<(&Land, &DynamicTile)>,
+	mut art_query: Query<(&mut Transform, &mut Visibility, &DynamicLandArt)>,
+) {
+	for (mut transform, mut visibility, art) in art_query.iter_mut() {
+		let Ok((land, tile)) = land_query.get(art.0) else {continue};
+		let mut iso_offset = cart_to_iso(land.shape.center() - tile.origin.center());
+		// Crumbling land shakes harder the closer it is to falling
+		if let LandBehaviour::Crumble{after} = tile.behaviour {
+			if land.solid && tile.stood > 0.0 {
+				iso_offset.x += (tile.age * 60.0).sin() * 3.0 * tile.stood / after;
+			}
+		}
+		transform.translation.x = iso_offset.x;
+		transform.translation.y = iso_offset.y;
+		*visibility = if land.solid {Visibility::Inherited} else {Visibility::Hidden};
+	}
+}
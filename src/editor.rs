@@ -0,0 +1,370 @@
+// Editor module, for laying out levels by hand in debug builds
+use bevy::{asset::{io::file::FileAssetReader, ron}, prelude::*, window::PrimaryWindow};
+
+use crate::{helper::{cart_to_iso, despawn_entities_without, iso_to_cart, GameState}, land::{LandGraph, LandShape, Pen}, level::{Level, LevelHandles, WinCondition}, setup::{ProgressTracker, ORTHO, REVERT}, wave::GoatSpawn};
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(OnEnter(GameState::Editor), (
+				despawn_entities_without::<Window>,
+				editor_setup,
+			).chain())
+			.add_systems(OnExit(GameState::Editor), (
+				despawn_entities_without::<Window>,
+				store_level,
+			))
+			.add_systems(Update, (
+				edit_level,
+				save_level,
+				draw_level,
+			).chain().run_if(in_state(GameState::Editor)))
+		;
+	}
+}
+
+// Unscaled copy of the level being edited, written back to the asset on save
+#[derive(Resource)]
+struct EditorLevel{
+	handle: Handle<Level>,
+	level: Level,
+	selected: Option<Selection>,
+	last_cursor: Vec2,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Selection {
+	Land(usize),
+	Goal(usize),
+	Island(usize),
+	PlayerSpawn,
+	GoatSpawn(usize),
+	SpitterSpawn(usize),
+}
+
+// Outlines and markers, respawned whenever the level changes
+#[derive(Component)]
+struct EditorMarker;
+
+const NEW_SHAPE_SIZE: Vec2 = Vec2::new(200.0, 200.0);
+const SPAWN_PICK_RADIUS: f32 = 20.0;
+const NEW_PEN_CAPACITY: usize = 10;
+
+fn editor_setup(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	progress_tracker: Res<ProgressTracker>,
+	level_handles: Res<LevelHandles>,
+	levels: Res<Assets<Level>>,
+) {
+	let handle = level_handles.for_mode(&progress_tracker.mode).clone();
+	let level = levels.get(&handle).cloned().unwrap_or_default();
+
+	for background in level.backgrounds.iter() {
+		commands.spawn((SpriteBundle {
+			transform: Transform::from_xyz(0.0, 0.0,  background.z),
+			texture: asset_server.load(&background.image),
+			sprite: Sprite {
+				color: Color::rgba(1.0, 1.0, 1.0, 0.5),
+				custom_size: Some(ORTHO),
+				..default()
+			},
+			..default()
+			},
+		));
+	}
+
+	commands.spawn((Text2dBundle {
+		transform: Transform::from_xyz(-ORTHO.x/2.0 + 20.0, ORTHO.y/2.0 - 20.0, 920.0),
+		text_anchor: bevy::sprite::Anchor::TopLeft,
+		text: Text::from_section(
+			"Left drag: move\nRight drag: resize rect / move polygon corner\nL G I: add land, goal, island\nK J: add goat, spitter spawn\nDelete: remove selected\nLeft Ctrl + S: save",
+			get_editor_text_style(&asset_server),
+		).with_justify(JustifyText::Left),
+		..default()
+		},
+	));
+
+	commands.insert_resource(EditorLevel{
+		handle,
+		level,
+		selected: None,
+		last_cursor: Vec2::ZERO,
+	});
+}
+
+fn get_editor_text_style(
+	asset_server: &Res<AssetServer>
+) -> TextStyle {
+	TextStyle {
+		font: asset_server.load("fonts/rony-siswadi-architect-1-font/smooth.ttf"),
+		font_size: 28.0,
+		color: Color::WHITE,
+	}
+}
+
+// Cursor position in the unscaled cart space used by level files
+fn cursor_location(
+	windows: &Query<&Window, With<PrimaryWindow>>,
+	camera_query: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec2> {
+	let cursor = windows.get_single().ok()?.cursor_position()?;
+	let (camera, camera_transform) = camera_query.get_single().ok()?;
+	let iso_loc = camera.viewport_to_world_2d(camera_transform, cursor)?;
+	Some(iso_to_cart(iso_loc) / REVERT)
+}
+
+// Spawn points take priority over shapes, and goals over the land beneath them
+fn pick(
+	level: &Level,
+	cursor: Vec2,
+) -> Option<Selection> {
+	let spawns = std::iter::once((Selection::PlayerSpawn, level.player_spawn))
+		.chain(level.goat_spawns.iter().enumerate().map(|(i, spawn)| (Selection::GoatSpawn(i), spawn.point)))
+		.chain(level.spitter_spawns.iter().enumerate().map(|(i, spawn)| (Selection::SpitterSpawn(i), *spawn)))
+		.collect::<Vec<_>>();
+	if let Some((selection, _)) = spawns.iter().find(|(_, spawn)| spawn.distance(cursor) < SPAWN_PICK_RADIUS) {
+		return Some(*selection);
+	}
+	level.goals.iter().enumerate().map(|(i, pen)| (Selection::Goal(i), &pen.shape))
+		.chain(level.islands.iter().enumerate().map(|(i, shape)| (Selection::Island(i), shape)))
+		.chain(level.land.iter().enumerate().map(|(i, shape)| (Selection::Land(i), shape)))
+		.find(|(_, shape)| shape.contains(cursor))
+		.map(|(selection, _)| selection)
+}
+
+fn edit_level(
+	mouse: Res<ButtonInput<MouseButton>>,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	windows: Query<&Window, With<PrimaryWindow>>,
+	camera_query: Query<(&Camera, &GlobalTransform)>,
+	mut editor: ResMut<EditorLevel>,
+) {
+	let Some(cursor) = cursor_location(&windows, &camera_query) else {return};
+	let mut changed = false;
+	let editor_level = editor.bypass_change_detection();
+
+	if mouse.just_pressed(MouseButton::Left) || mouse.just_pressed(MouseButton::Right) {
+		editor_level.selected = pick(&editor_level.level, cursor);
+		editor_level.last_cursor = cursor;
+		changed = true;
+	}
+
+	if let Some(selected) = editor_level.selected {
+		let delta = cursor - editor_level.last_cursor;
+		if mouse.pressed(MouseButton::Left) && delta != Vec2::ZERO {
+			match selected {
+				Selection::Land(i) => move_shape(&mut editor_level.level.land[i], delta),
+				Selection::Goal(i) => move_shape(&mut editor_level.level.goals[i].shape, delta),
+				Selection::Island(i) => move_shape(&mut editor_level.level.islands[i], delta),
+				Selection::PlayerSpawn => editor_level.level.player_spawn += delta,
+				Selection::GoatSpawn(i) => editor_level.level.goat_spawns[i].point += delta,
+				Selection::SpitterSpawn(i) => editor_level.level.spitter_spawns[i] += delta,
+			}
+			editor_level.last_cursor = cursor;
+			changed = true;
+		} else if mouse.pressed(MouseButton::Right) && delta != Vec2::ZERO {
+			match selected {
+				Selection::Land(i) => reshape(&mut editor_level.level.land[i], cursor),
+				Selection::Goal(i) => reshape(&mut editor_level.level.goals[i].shape, cursor),
+				Selection::Island(i) => reshape(&mut editor_level.level.islands[i], cursor),
+				_ => (),
+			}
+			editor_level.last_cursor = cursor;
+			changed = true;
+		}
+
+		if keyboard.just_pressed(KeyCode::Delete) || keyboard.just_pressed(KeyCode::Backspace) {
+			match selected {
+				Selection::Land(i) => {editor_level.level.land.remove(i);},
+				Selection::Goal(i) => {editor_level.level.goals.remove(i);},
+				Selection::Island(i) => {editor_level.level.islands.remove(i);},
+				Selection::PlayerSpawn => (),
+				Selection::GoatSpawn(i) => {editor_level.level.goat_spawns.remove(i);},
+				Selection::SpitterSpawn(i) => {editor_level.level.spitter_spawns.remove(i);},
+			}
+			editor_level.selected = None;
+			changed = true;
+		}
+	}
+
+	let new_shape = LandShape::Rect{center: cursor, size: NEW_SHAPE_SIZE};
+	if keyboard.just_pressed(KeyCode::KeyL) {
+		editor_level.level.land.push(new_shape);
+		editor_level.selected = Some(Selection::Land(editor_level.level.land.len() - 1));
+		changed = true;
+	} else if keyboard.just_pressed(KeyCode::KeyG) {
+		// AllPens levels won't load with a pen that has neither a capacity nor a required count
+		let capacity = (editor_level.level.win == WinCondition::AllPens).then_some(NEW_PEN_CAPACITY);
+		editor_level.level.goals.push(Pen{capacity, ..Pen::new(new_shape)});
+		editor_level.selected = Some(Selection::Goal(editor_level.level.goals.len() - 1));
+		changed = true;
+	} else if keyboard.just_pressed(KeyCode::KeyI) {
+		editor_level.level.islands.push(new_shape);
+		editor_level.selected = Some(Selection::Island(editor_level.level.islands.len() - 1));
+		changed = true;
+	} else if keyboard.just_pressed(KeyCode::KeyK) {
+		editor_level.level.goat_spawns.push(GoatSpawn::new(cursor));
+		editor_level.selected = Some(Selection::GoatSpawn(editor_level.level.goat_spawns.len() - 1));
+		changed = true;
+	} else if keyboard.just_pressed(KeyCode::KeyJ) {
+		editor_level.level.spitter_spawns.push(cursor);
+		editor_level.selected = Some(Selection::SpitterSpawn(editor_level.level.spitter_spawns.len() - 1));
+		changed = true;
+	}
+
+	if changed {
+		editor.set_changed();
+	}
+}
+
+fn move_shape(
+	shape: &mut LandShape,
+	delta: Vec2,
+) {
+	match shape {
+		LandShape::Rect{center, ..} => *center += delta,
+		LandShape::Polygon(points) => points.iter_mut().for_each(|point| *point += delta),
+	}
+}
+
+// Rects grow about their centre, polygons move the corner nearest the cursor
+fn reshape(
+	shape: &mut LandShape,
+	cursor: Vec2,
+) {
+	match shape {
+		LandShape::Rect{center, size} => *size = (cursor - *center).abs() * 2.0,
+		LandShape::Polygon(points) => {
+			if let Some(point) = points.iter_mut().min_by(|a, b| a.distance(cursor).total_cmp(&b.distance(cursor))) {
+				*point = cursor;
+			}
+		},
+	}
+}
+
+fn save_level(
+	keyboard: Res<ButtonInput<KeyCode>>,
+	asset_server: Res<AssetServer>,
+	editor: Res<EditorLevel>,
+	mut levels: ResMut<Assets<Level>>,
+) {
+	if keyboard.pressed(KeyCode::ControlLeft) && keyboard.just_pressed(KeyCode::KeyS) {
+		// Written out, it would never load again
+		if let Some(problem) = editor.level.problem() {
+			error!("Not saving level: {}", problem);
+			return;
+		}
+		levels.insert(editor.handle.id(), editor.level.clone());
+		let Some(asset_path) = asset_server.get_path(editor.handle.id()) else {return};
+		let path = FileAssetReader::new("assets").root_path().join(asset_path.path());
+		let config = ron::ser::PrettyConfig::new().indentor("\t".to_string());
+		let result = ron::ser::to_string_pretty(&editor.level, config)
+			.map_err(|error| error.to_string())
+			.and_then(|text| std::fs::write(&path, text).map_err(|error| error.to_string()));
+		match result {
+			Ok(()) => info!("Saved level to {}", path.display()),
+			Err(error) => error!("Could not save level to {}: {}", path.display(), error),
+		}
+	}
+}
+
+// Leaving the editor keeps the edits for the next game, even if unsaved
+fn store_level(
+	editor: Option<Res<EditorLevel>>,
+	mut levels: ResMut<Assets<Level>>,
+) {
+	if let Some(editor) = editor {
+		levels.insert(editor.handle.id(), editor.level.clone());
+	}
+}
+
+fn draw_level(
+	mut commands: Commands,
+	editor: Res<EditorLevel>,
+	marker_query: Query<Entity, With<EditorMarker>>,
+) {
+	if !editor.is_changed() {
+		return;
+	}
+	for entity in marker_query.iter() {
+		commands.entity(entity).despawn_recursive();
+	}
+
+	let selected_color = |selection: Selection, color: Color| {
+		if editor.selected == Some(selection) {Color::YELLOW} else {color}
+	};
+	let shapes = editor.level.land.iter().enumerate().map(|(i, shape)| (shape, selected_color(Selection::Land(i), Color::CYAN)))
+		.chain(editor.level.goals.iter().enumerate().map(|(i, pen)| (&pen.shape, selected_color(Selection::Goal(i), Color::RED))))
+		.chain(editor.level.islands.iter().enumerate().map(|(i, shape)| (shape, selected_color(Selection::Island(i), Color::GRAY))))
+		.chain(editor.level.dynamic_land.iter().map(|dynamic| (&dynamic.shape, Color::ORANGE)))
+		.chain(editor.level.zones.iter().map(|zone| (&zone.shape, Color::TEAL)))
+		.chain(editor.level.elevations.iter().map(|elevation| (&elevation.shape, Color::PURPLE)));
+	for (shape, color) in shapes {
+		let points = shape.points();
+		for i in 0..points.len() {
+			spawn_line(&mut commands, points[i], points[(i+1)%points.len()], color, 4.0);
+		}
+	}
+
+	let spawns = std::iter::once((editor.level.player_spawn, selected_color(Selection::PlayerSpawn, Color::BLUE)))
+		.chain(editor.level.goat_spawns.iter().enumerate().map(|(i, spawn)| (spawn.point, selected_color(Selection::GoatSpawn(i), Color::WHITE))))
+		.chain(editor.level.spitter_spawns.iter().enumerate().map(|(i, spawn)| (*spawn, selected_color(Selection::SpitterSpawn(i), Color::PURPLE))));
+	for (spawn, color) in spawns {
+		spawn_marker(&mut commands, spawn, color, 0.0);
+	}
+
+	// Each tile points at its anchor, the way a stray goat on it would head
+	let land_graph = LandGraph::from_level(&editor.level);
+	for (tile, anchor) in land_graph.tiles.iter().zip(land_graph.flow_field()) {
+		spawn_line(&mut commands, tile.center(), anchor, Color::GREEN, 2.0);
+		spawn_marker(&mut commands, anchor, Color::GREEN, std::f32::consts::FRAC_PI_4);
+	}
+}
+
+// Line between two unscaled cart points, drawn in iso space
+fn spawn_line(
+	commands: &mut Commands,
+	start: Vec2,
+	end: Vec2,
+	color: Color,
+	width: f32,
+) {
+	let (iso_start, iso_end) = (cart_to_iso(start * REVERT), cart_to_iso(end * REVERT));
+	let offset = iso_end - iso_start;
+	commands.spawn((SpriteBundle {
+		transform: Transform::from_translation(((iso_start + iso_end)/2.0).extend(900.0))
+			.with_rotation(Quat::from_rotation_z(offset.y.atan2(offset.x))),
+		sprite: Sprite {
+			color,
+			custom_size: Some(Vec2::new(offset.length(), width)),
+			..default()
+		},
+		..default()
+		},
+		EditorMarker,
+	));
+}
+
+fn spawn_marker(
+	commands: &mut Commands,
+	location: Vec2,
+	color: Color,
+	rotation: f32,
+) {
+	let iso_loc = cart_to_iso(location * REVERT);
+	commands.spawn((SpriteBundle {
+		transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 905.0)
+			.with_rotation(Quat::from_rotation_z(rotation)),
+		sprite: Sprite {
+			color,
+			custom_size: Some(Vec2::new(16.0, 16.0)),
+			..default()
+		},
+		..default()
+		},
+		EditorMarker,
+	));
+}
@@ -0,0 +1,180 @@
+// Elevation module, for raised ground, ramps and the drops between them
+use bevy::{prelude::*, transform::TransformSystem};
+use serde::{Deserialize, Serialize};
+
+use crate::{goat::{GoatMovement, GoatNumbers, GoatState, GoatTransition, LossCause}, helper::{iso_to_cart, GameState}, land::{Ground, LandShape}, level::{CurrentLevel, LevelSelect}, player::PlayerMovement, terrain::{build_raised_terrain, TerrainTiles}};
+
+pub struct ElevationPlugin;
+
+impl Plugin for ElevationPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(OnEnter(GameState::Game), (
+				elevation_setup.after(LevelSelect),
+			))
+			.add_systems(PreUpdate, (
+				lower_to_ground,
+			).run_if(in_state(GameState::Game)))
+			.add_systems(Update, (
+				track_elevation,
+			).run_if(in_state(GameState::Game)))
+			.add_systems(PostUpdate, (
+				raise_to_height.before(TransformSystem::TransformPropagate),
+			).run_if(in_state(GameState::Game)))
+		;
+	}
+}
+
+// Highest step goats and the player can walk up, anything more is a cliff
+pub const MAX_STEP: f32 = 6.0;
+// Highest step the player can make in the middle of a jump
+pub const JUMP_CLIMB: f32 = 40.0;
+// Falling further than this stuns, and goats don't survive past DROP_HURT
+const DROP_STUN: f32 = 30.0;
+const DROP_HURT: f32 = 90.0;
+const STUN_SECONDS: f32 = 1.0;
+
+// Level data for raised ground. Heights are iso pixels of unscaled level data,
+// so scale with REVERT like the shapes do, and a ramp rises from nothing on one
+// side of the shape to the full height on the other.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Elevation{
+	pub shape: LandShape,
+	pub height: f32,
+	// Cart direction the ramp rises in, flat when None
+	#[serde(default)]
+	pub ramp: Option<Vec2>,
+}
+
+impl Elevation {
+	pub fn height_at(
+		&self,
+		point: Vec2,
+	) -> Option<f32> {
+		if !self.shape.contains(point) {
+			return None;
+		}
+		let Some(direction) = self.ramp.and_then(|ramp| ramp.try_normalize()) else {
+			return Some(self.height);
+		};
+		let (min, max) = self.shape.points().iter()
+			.map(|corner| corner.dot(direction))
+			.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), along| (min.min(along), max.max(along)));
+		Some(self.height * ((point.dot(direction) - min) / (max - min)).clamp(0.0, 1.0))
+	}
+
+	pub fn scaled(
+		&self,
+		scale: f32,
+	) -> Elevation {
+		Elevation{
+			shape: self.shape.scaled(scale),
+			height: self.height * scale,
+			ramp: self.ramp,
+		}
+	}
+}
+
+// Highest ground under the point, zero away from any elevation
+pub fn ground_height(
+	elevations: &[Elevation],
+	point: Vec2,
+) -> f32 {
+	elevations.iter()
+		.filter_map(|elevation| elevation.height_at(point))
+		.fold(0.0, f32::max)
+}
+
+// Goats and the player are drawn raised by the height of the ground they
+// stand on. The offset is only applied between frames, so movement code
+// always sees positions on the flat ground plane.
+#[derive(Component)]
+pub struct Elevated{
+	// Ground height when last on the ground, drops are measured from here
+	pub height: f32,
+	offset: f32,
+}
+
+fn feet(
+	transform: &Transform,
+) -> Vec2 {
+	iso_to_cart(transform.translation.xy() - Vec2::new(0.0, 32.0))
+}
+
+fn elevation_setup(
+	mut commands: Commands,
+	terrain_tiles: Res<TerrainTiles>,
+	level: Res<CurrentLevel>,
+) {
+	let elevations = &level.0.elevations;
+	commands.spawn(SpatialBundle::default()).with_children(|parent| {
+		for elevation in elevations.iter() {
+			build_raised_terrain(parent, &terrain_tiles, &elevation.shape, |point| ground_height(elevations, point));
+		}
+	});
+}
+
+// Goats and the player that haven't been given an Elevated yet
+type Untracked = (Or<(With<GoatMovement>, With<PlayerMovement>)>, Without<Elevated>);
+
+fn track_elevation(
+	mut commands: Commands,
+	untracked_query: Query<(Entity, &Transform), Untracked>,
+	ground: Ground,
+) {
+	for (entity, transform) in untracked_query.iter() {
+		commands.entity(entity).insert(Elevated{
+			height: ground.height_at(feet(transform)),
+			offset: 0.0,
+		});
+	}
+}
+
+fn lower_to_ground(
+	mut elevated_query: Query<(&mut Transform, &mut Elevated)>,
+) {
+	for (mut transform, mut elevated) in elevated_query.iter_mut() {
+		transform.translation.y -= elevated.offset;
+		elevated.offset = 0.0;
+	}
+}
+
+fn raise_to_height(
+	mut commands: Commands,
+	mut goat_query: Query<(Entity, &mut Transform, &mut GoatMovement, &mut Elevated), Without<PlayerMovement>>,
+	mut player_query: Query<(&mut Transform, &mut PlayerMovement, &mut Elevated), Without<GoatMovement>>,
+	mut goat_numbers: ResMut<GoatNumbers>,
+	mut transitions: EventWriter<GoatTransition>,
+	ground: Ground,
+) {
+	for (entity, mut transform, mut goat_movement, mut elevated) in goat_query.iter_mut() {
+		let height = ground.height_at(feet(&transform));
+		let drop = elevated.height - height;
+		if goat_movement.state() != GoatState::Grabbed && goat_movement.in_play() {
+			if drop > DROP_HURT {
+				commands.entity(entity).despawn_recursive();
+				goat_numbers.lose(LossCause::Dropped);
+				continue;
+			}
+			if drop > DROP_STUN {
+				goat_movement.set_state(entity, GoatState::Stunned, &mut transitions);
+			}
+		}
+		elevated.height = height;
+		elevated.offset = height;
+		transform.translation.y += height;
+	}
+
+	for (mut transform, mut player_movement, mut elevated) in player_query.iter_mut() {
+		let height = ground.height_at(feet(&transform));
+		// A jump only counts as a drop once the player lands
+		if !player_movement.airborne() {
+			if elevated.height - height > DROP_STUN {
+				player_movement.stun(STUN_SECONDS);
+			}
+			elevated.height = height;
+		}
+		elevated.offset = height;
+		transform.translation.y += height;
+	}
+}
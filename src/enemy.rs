@@ -1,215 +1,1109 @@
 // Enemy module, for handling enemy movement and interactions
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
 
-use crate::{goat::{GoatMovement, GoatNumbers}, helper::{cart_to_iso, iso_to_cart, GameState}, setup::{ORTHO, REVERT}};
+use crate::{goat::{GoatMovement, GoatNumbers, GoatState, GoatTransition, LossCause, Scare}, helper::{cart_to_iso, iso_to_cart, GameState}, land::{LandShape, Pens}, player::PlayerMovement, rng::{GameRng, RngStream}, setup::{BellEvent, ORTHO, REVERT}, spatial::SpatialIndex};
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
 	fn build(&self, app: &mut App) {
 		app
+			.insert_resource(ScreenShake{
+				timer: Timer::from_seconds(0.0, TimerMode::Once),
+				strength: 0.0,
+			})
 			.add_systems(Update, (
-				goatbird_movement,
-				spitter_stuff,
-				arm_stuff,
-			).run_if(in_state(GameState::Game)))
+				shake_camera,
+			))
+			.add_systems(Update, (
+				enemy_bell_reactions,
+				enemy_upkeep,
+				(
+					goatbird_movement,
+					spitter_stuff,
+					spit_stuff,
+					arm_stuff,
+					burrower_stuff,
+				),
+			).chain().run_if(in_state(GameState::Game)))
 		;
 	}
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EnemyKind {
+	Goatbird,
+	Spitter,
+	Spit,
+	Arm,
+	Burrower,
+}
+
+// What an enemy does when a bell rings within range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BellReaction {
+	Ignore,
+	// Turns away from the bell and stops being a threat
+	Scare,
+	// Frozen for this many seconds. No enemy's table uses it at the moment,
+	// it's kept so one can without touching the systems.
+	#[allow(dead_code)]
+	Stun(f32),
+	// Sent straight away from the bell, once
+	Reflect,
+	// Driven up out of hiding before it's ready, letting go of anything it holds
+	Flush,
+	Destroy,
+}
+
+impl EnemyKind {
+	// Reactions to Beckon, Warn, Gather, Energize and Dance, in bell order
+	pub fn bell_reactions(
+		self,
+	) -> [BellReaction; 5] {
+		match self {
+			EnemyKind::Goatbird => [BellReaction::Scare, BellReaction::Scare, BellReaction::Scare, BellReaction::Ignore, BellReaction::Ignore],
+			EnemyKind::Spitter => [BellReaction::Destroy, BellReaction::Destroy, BellReaction::Destroy, BellReaction::Ignore, BellReaction::Ignore],
+			EnemyKind::Spit => [BellReaction::Destroy, BellReaction::Reflect, BellReaction::Destroy, BellReaction::Ignore, BellReaction::Ignore],
+			EnemyKind::Arm => [BellReaction::Ignore; 5],
+			EnemyKind::Burrower => [BellReaction::Ignore, BellReaction::Flush, BellReaction::Ignore, BellReaction::Ignore, BellReaction::Ignore],
+		}
+	}
+
+	pub fn bell_reaction(
+		self,
+		bell: usize,
+	) -> BellReaction {
+		self.bell_reactions().get(bell).copied().unwrap_or(BellReaction::Ignore)
+	}
+}
+
+// State every enemy shares, next to the component for its own behaviour
 #[derive(Component)]
-pub struct GoatbirdMovement{
+pub struct Enemy{
+	pub kind: EnemyKind,
 	pub cart_transform: Vec2,
 	pub velocity: Vec2,
+	// Despawned once this reaches zero
+	pub health: f32,
+	// Set by a bell, scared enemies stop going after goats
+	pub scared: bool,
+	// A bell it reacts to rang in range this frame
+	pub belled: bool,
+	pub stun: Timer,
+}
+
+impl Enemy {
+	pub fn new(
+		kind: EnemyKind,
+		cart_transform: Vec2,
+		velocity: Vec2,
+	) -> Enemy {
+		Enemy{
+			kind,
+			cart_transform,
+			velocity,
+			health: 1.0,
+			scared: false,
+			belled: false,
+			stun: Timer::from_seconds(0.0, TimerMode::Once),
+		}
+	}
+
+	pub fn stunned(
+		&self,
+	) -> bool {
+		!self.stun.finished()
+	}
+}
+
+#[derive(Component)]
+pub struct GoatbirdMovement{
 	pub speed: f32,
 	pub feeding: bool,
 	pub feed_timer: Timer,
-	pub scared: bool,
+	// Goat being carried off, only lost once it's off the screen
+	pub carrying: Option<Entity>,
+	// Goats it has hold of while feeding, all let go again apart from one it carries off
+	pub grabbed: Vec<Entity>,
+	// A bell or a jump can still make it drop the goat until this runs out
+	pub drop_timer: Timer,
 }
 
+// Seconds after lifting a goat that it can still be made to let go
+const DROP_WINDOW: f32 = 1.5;
+// Cart distance from a carried goat a jumping player knocks it loose
+const JUMP_REACH: f32 = 40.0;
+
 #[derive(Component)]
 pub struct Spitter{
-	pub cart_transform: Vec2,
+	pub profile: SpitterProfile,
 	pub charge_timer: Timer,
 	pub spit_timer: Timer,
+	// Spits so far in this burst
+	pub shots: usize,
+}
+
+// Which goat a spitter goes for
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SpitterTarget {
+	#[default]
+	Nearest,
+	// Fewest other goats within ISOLATION_RADIUS, nearest first
+	Isolated,
+	// Closest to a pen that still has room
+	NearestPen,
+}
+
+// Wave data for how a spitter aims and fires
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct SpitterProfile{
+	pub target: SpitterTarget,
+	// How far along a walking goat's path to aim, 0 aims where it is now and 1 where the spit meets it
+	pub lead: f32,
+	// Degrees either side of the aim a spit can stray
+	pub spread: f32,
+	// Spits before charging up again, keeps going when None
+	pub burst: Option<usize>,
+	// Cart pixels per second
+	pub spit_speed: f32,
+}
+
+impl Default for SpitterProfile {
+	fn default() -> SpitterProfile {
+		SpitterProfile{
+			target: SpitterTarget::Nearest,
+			lead: 1.0,
+			spread: 5.0,
+			burst: None,
+			spit_speed: 300.0,
+		}
+	}
+}
+
+const ISOLATION_RADIUS: f32 = 120.0;
+const SPITTER_CHARGE: f32 = 3.0;
+const SPIT_EVERY: f32 = 0.5;
+
+#[derive(Component)]
+pub struct Spit;
+
+#[derive(Component)]
+pub struct Burrower{
+	state: BurrowState,
+	timer: Timer,
+	// Goat held since erupting, dragged under once the timer runs out
+	prey: Option<Entity>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BurrowState {
+	// Mound growing, nothing can set it off yet
+	Digging,
+	// Waiting for a goat to come within AMBUSH_RADIUS, giving up after LURK_SECONDS
+	Lurking,
+	// Mound shaking, the last chance to get goats clear
+	Erupting,
+	// Up out of the ground, with or without a goat
+	Surfaced,
+}
+
+// Dirt mound over a burrower, the body shows once it's up
+#[derive(Component)]
+struct BurrowerBody;
+
+const DIG_SECONDS: f32 = 2.0;
+const LURK_SECONDS: f32 = 20.0;
+const ERUPT_SECONDS: f32 = 0.6;
+const HOLD_SECONDS: f32 = 1.2;
+const AMBUSH_RADIUS: f32 = 70.0;
+const GRAB_RADIUS: f32 = 48.0;
+const MOUND_SIZE: Vec2 = Vec2::new(96.0, 48.0);
+const DIRT: Color = Color::rgb(0.45, 0.32, 0.2);
+
+// Level data for one arm, slamming its zones in turn
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ArmAttack{
+	pub zones: Vec<LandShape>,
+	// Seconds between slams, and how long the arm stays down after one
+	#[serde(default = "default_arm_wait")]
+	pub wait: f32,
+	#[serde(default = "default_arm_down")]
+	pub down: f32,
+	// Seconds at the end of each wait that the slam's shadow grows in
+	#[serde(default = "default_arm_telegraph")]
+	pub telegraph: f32,
+	// Extra seconds before the first slam, so several arms can take turns
+	#[serde(default)]
+	pub offset: f32,
+	// Slams before the arm leaves, keeps going when None
+	#[serde(default)]
+	pub count: Option<usize>,
+}
+
+fn default_arm_wait() -> f32 {
+	6.1
+}
+
+pub fn default_arm_down() -> f32 {
+	1.3
+}
+
+fn default_arm_telegraph() -> f32 {
+	1.5
+}
+
+// Where the slam lands in the arm art, other zones move the art to match
+const ARM_ART_ZONE: Vec2 = Vec2::new(150.0 * REVERT, 300.0 * REVERT);
+
+impl Default for ArmAttack {
+	fn default() -> ArmAttack {
+		ArmAttack{
+			zones: vec![LandShape::Rect{center: Vec2::new(150.0, 300.0), size: Vec2::new(200.0, 300.0)}],
+			wait: default_arm_wait(),
+			down: default_arm_down(),
+			telegraph: default_arm_telegraph(),
+			offset: 0.0,
+			count: None,
+		}
+	}
+}
+
+impl ArmAttack {
+	pub fn scaled(
+		&self,
+		scale: f32,
+	) -> ArmAttack {
+		ArmAttack{
+			zones: self.zones.iter().map(|zone| zone.scaled(scale)).collect(),
+			..self.clone()
+		}
+	}
+}
+
+#[derive(Component)]
+pub struct Arm{
+	attack: ArmAttack,
+	wait_timer: Timer,
+	slam_timer: Timer,
+	// Index into the attack's zones for the coming slam
+	zone: usize,
+	slams: usize,
+}
+
+// Telegraph under an arm, grows over the zone before a slam
+#[derive(Component)]
+struct ArmShadow;
+
+#[derive(Resource)]
+pub struct ScreenShake{
+	timer: Timer,
+	strength: f32,
+}
+
+impl ScreenShake {
+	pub fn start(
+		&mut self,
+		seconds: f32,
+		strength: f32,
+	) {
+		self.timer = Timer::from_seconds(seconds, TimerMode::Once);
+		self.strength = strength;
+	}
+}
+
+// Spawns any kind of enemy with its art and components
+#[derive(SystemParam)]
+pub struct EnemySpawner<'w, 's> {
+	commands: Commands<'w, 's>,
+	asset_server: Res<'w, AssetServer>,
+	texture_atlas_layouts: ResMut<'w, Assets<TextureAtlasLayout>>,
+}
+
+impl<'w, 's> EnemySpawner<'w, 's> {
+	pub fn spawn(
+		&mut self,
+		kind: EnemyKind,
+		cart_loc: Vec2,
+		velocity: Vec2,
+	) -> Entity {
+		let iso_loc = cart_to_iso(cart_loc);
+		match kind {
+			EnemyKind::Goatbird => self.commands.spawn((SpriteBundle {
+				transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 800.0),
+				texture: self.asset_server.load("goatbird.png"),
+				sprite: Sprite {
+					custom_size: Some(Vec2::new(64.0, 64.0)),
+					..default()
+				},
+				..default()
+				},
+				Enemy::new(kind, cart_loc, velocity),
+				GoatbirdMovement {
+					speed: 110.0,
+					feeding: false,
+					feed_timer: Timer::from_seconds(3.0, TimerMode::Once),
+					carrying: None,
+					grabbed: Vec::new(),
+					drop_timer: Timer::from_seconds(DROP_WINDOW, TimerMode::Once),
+				}
+			)).id(),
+			EnemyKind::Spitter => self.spawn_spitter(cart_loc, SpitterProfile::default()),
+			EnemyKind::Spit => self.commands.spawn((SpriteBundle {
+				transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 800.0),
+				texture: self.asset_server.load("spit.png"),
+				sprite: Sprite {
+					color: Color::RED,
+					custom_size: Some(Vec2::new(32.0, 32.0)),
+					..default()
+				},
+				..default()
+				},
+				Enemy::new(kind, cart_loc, velocity),
+				Spit,
+			)).id(),
+			// Under goats, with the body drawn over them once it's up
+			EnemyKind::Burrower => self.commands.spawn((SpriteBundle {
+				transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 590.0),
+				texture: self.asset_server.load("shadow.png"),
+				sprite: Sprite {
+					color: DIRT,
+					custom_size: Some(Vec2::ZERO),
+					..default()
+				},
+				..default()
+				},
+				Enemy::new(kind, cart_loc, velocity),
+				Burrower{
+					state: BurrowState::Digging,
+					timer: Timer::from_seconds(DIG_SECONDS, TimerMode::Once),
+					prey: None,
+				}
+			)).with_children(|parent| {
+				parent.spawn((SpriteBundle {
+					transform: Transform::from_xyz(0.0, 32.0, 20.0),
+					texture: self.asset_server.load("burrower.png"),
+					sprite: Sprite {
+						custom_size: Some(Vec2::new(96.0, 96.0)),
+						..default()
+					},
+					visibility: Visibility::Hidden,
+					..default()
+					},
+					BurrowerBody,
+				));
+			}).id(),
+			// Arms slam where their attack says, so this one gets the default
+			EnemyKind::Arm => self.spawn_arm(&ArmAttack::default().scaled(REVERT)),
+		}
+	}
+
+	// Spitter that aims and fires the way the wave says
+	pub fn spawn_spitter(
+		&mut self,
+		cart_loc: Vec2,
+		profile: SpitterProfile,
+	) -> Entity {
+		// Stands a little up the screen from its spawn point
+		let iso_loc = cart_to_iso(cart_loc) + Vec2::new(0.0, 16.0);
+		self.commands.spawn((SpriteBundle {
+			transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 800.0),
+			texture: self.asset_server.load("spitter.png"),
+			sprite: Sprite {
+				custom_size: Some(Vec2::new(128.0, 128.0)),
+				..default()
+			},
+			..default()
+			},
+			Enemy::new(EnemyKind::Spitter, iso_to_cart(iso_loc), Vec2::ZERO),
+			Spitter{
+				profile,
+				charge_timer: Timer::from_seconds(SPITTER_CHARGE, TimerMode::Once),
+				spit_timer: Timer::from_seconds(SPIT_EVERY, TimerMode::Repeating),
+				shots: 0,
+			}
+		)).id()
+	}
+
+	// Arm with a scaled attack from the level
+	pub fn spawn_arm(
+		&mut self,
+		attack: &ArmAttack,
+	) -> Entity {
+		let texture_atlas_layout = self.texture_atlas_layouts.add(TextureAtlasLayout::from_grid(Vec2::new(1920.0, 1080.0), 2, 1, Some(Vec2::ZERO), Some(Vec2::ZERO)));
+		self.commands.spawn((SpriteSheetBundle {
+			transform: Transform::from_xyz(0.0, 0.0, 553.0),
+			texture: self.asset_server.load("arm.png"),
+			atlas: TextureAtlas{
+				layout: texture_atlas_layout,
+				index: 1,
+			},
+			sprite: Sprite {
+				custom_size: Some(ORTHO),
+				..default()
+			},
+			..default()
+			},
+			Enemy::new(EnemyKind::Arm, attack.zones.first().map_or(Vec2::ZERO, |zone| zone.center()), Vec2::ZERO),
+			Arm{
+				attack: attack.clone(),
+				wait_timer: Timer::from_seconds(attack.offset + attack.wait, TimerMode::Once),
+				slam_timer: Timer::from_seconds(attack.down, TimerMode::Once),
+				zone: 0,
+				slams: 0,
+			}
+		)).with_children(|parent| {
+			parent.spawn((SpriteBundle {
+				transform: Transform::from_xyz(0.0, 0.0, -1.0),
+				texture: self.asset_server.load("shadow.png"),
+				visibility: Visibility::Hidden,
+				..default()
+				},
+				ArmShadow,
+			));
+		}).id()
+	}
+}
+
+fn enemy_bell_reactions(
+	mut bells: EventReader<BellEvent>,
+	mut enemy_query: Query<&mut Enemy>,
+	spatial_index: Res<SpatialIndex>,
+	mut game_rng: ResMut<GameRng>,
+) {
+	for mut enemy in enemy_query.iter_mut() {
+		if enemy.belled {
+			enemy.belled = false;
+		}
+	}
+	for bell in bells.read() {
+		for (entity, _) in spatial_index.enemies.nearby(bell.cart_location, bell.range) {
+			let Ok(mut enemy) = enemy_query.get_mut(entity) else {continue};
+			let to_bell = bell.cart_location - enemy.cart_transform;
+			let distance = to_bell.length();
+			if distance == 0.0 || distance >= bell.range {
+				continue;
+			}
+			let reaction = enemy.kind.bell_reaction(bell.selected_bell);
+			enemy.belled |= reaction != BellReaction::Ignore;
+			match reaction {
+				BellReaction::Ignore => (),
+				BellReaction::Scare => {
+					if !enemy.scared {
+						let theta = enemy.velocity.normalize().angle_between(to_bell.normalize());
+						let theta_offset = ((game_rng.random(RngStream::Bells) - 0.5) * distance / bell.range * 60.0).to_radians();
+						enemy.velocity = -enemy.velocity.rotate(Vec2::from_angle(theta + theta_offset));
+						enemy.scared = true;
+					}
+				},
+				BellReaction::Stun(seconds) => enemy.stun = Timer::from_seconds(seconds, TimerMode::Once),
+				BellReaction::Reflect => {
+					if !enemy.scared {
+						enemy.velocity = -to_bell.normalize() * enemy.velocity.length();
+						enemy.scared = true;
+					}
+				},
+				BellReaction::Flush => enemy.scared = true,
+				BellReaction::Destroy => enemy.health = 0.0,
+			}
+		}
+	}
+}
+
+fn enemy_upkeep(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut enemy_query: Query<(Entity, &mut Enemy)>,
+) {
+	for (entity, mut enemy) in enemy_query.iter_mut() {
+		if enemy.health <= 0.0 {
+			commands.entity(entity).despawn_recursive();
+			continue;
+		}
+		enemy.stun.tick(time.delta());
+	}
+}
+
+// Takes goats an enemy gets out of the level, frightening the goats nearby
+#[derive(SystemParam)]
+struct GoatLosses<'w, 's> {
+	commands: Commands<'w, 's>,
+	goat_numbers: ResMut<'w, GoatNumbers>,
+	scares: EventWriter<'w, Scare>,
+}
+
+impl<'w, 's> GoatLosses<'w, 's> {
+	fn lose(
+		&mut self,
+		goat: Entity,
+		cart_loc: Vec2,
+		cause: LossCause,
+	) {
+		self.commands.entity(goat).despawn_recursive();
+		self.goat_numbers.lose(cause);
+		self.scares.send(Scare::death(cart_loc));
+	}
+}
+
+// Goats a goatbird can grab and carry
+type GoatbirdPrey<'w, 's> = Query<'w, 's, (Entity, &'static mut Transform, &'static mut GoatMovement), Without<GoatbirdMovement>>;
+
+// Lets go of every goat grabbed while feeding, apart from the one being carried,
+// otherwise they'd stay held with nothing to put them down
+fn release_grabbed(
+	goatbird_movement: &mut GoatbirdMovement,
+	goat_query: &mut GoatbirdPrey,
+	transitions: &mut EventWriter<GoatTransition>,
+) {
+	let carrying = goatbird_movement.carrying;
+	for goat_entity in goatbird_movement.grabbed.drain(..) {
+		if Some(goat_entity) == carrying {
+			continue;
+		}
+		let Ok((_, _, mut goat_movement)) = goat_query.get_mut(goat_entity) else {continue};
+		if goat_movement.state() == GoatState::Grabbed {
+			goat_movement.set_state(goat_entity, GoatState::Stunned, transitions);
+		}
+	}
 }
 
 fn goatbird_movement(
 	time: Res<Time>,
-	mut commands: Commands,
-	asset_server: Res<AssetServer>,
-	mut goatbird_query: Query<(Entity, &mut Transform, &mut GoatbirdMovement)>,
-	mut goat_query: Query<(Entity, &mut Transform, &mut GoatMovement), Without<GoatbirdMovement>>,
-	mut goat_numbers: ResMut<GoatNumbers>,
+	mut goatbird_query: Query<(Entity, &mut Transform, &mut Enemy, &mut GoatbirdMovement)>,
+	mut goat_query: GoatbirdPrey,
+	player_query: Query<&PlayerMovement>,
+	mut losses: GoatLosses,
+	spatial_index: Res<SpatialIndex>,
+	mut transitions: EventWriter<GoatTransition>,
 ) {
-	for (goatbird_entity, mut goatbird_transform, mut goatbird_movement) in goatbird_query.iter_mut() {
-		if !goatbird_movement.scared {
+	// Goatbirds go for the middle of a goat rather than its feet
+	let goat_middle = iso_to_cart(Vec2::new(0.0, 16.0));
+	for (goatbird_entity, mut goatbird_transform, mut enemy, mut goatbird_movement) in goatbird_query.iter_mut() {
+		// A bell or a jump early on makes it let go, and the goat lands wherever it is
+		if let Some(goat_entity) = goatbird_movement.carrying {
+			goatbird_movement.drop_timer.tick(time.delta());
+			if let Ok((_, _, mut goat_movement)) = goat_query.get_mut(goat_entity) {
+				let knocked = player_query.iter().any(|player| player.airborne() && player.cart_transform.distance(goat_movement.cart_transform) < JUMP_REACH);
+				if !goatbird_movement.drop_timer.finished() && (enemy.belled || knocked) {
+					goat_movement.set_state(goat_entity, GoatState::Stunned, &mut transitions);
+					goatbird_movement.carrying = None;
+				} else if goat_movement.state() != GoatState::Grabbed {
+					// Something else got the goat out of its grip
+					goatbird_movement.carrying = None;
+				}
+			} else {
+				goatbird_movement.carrying = None;
+			}
+		}
+		if enemy.stunned() {
+			continue;
+		}
+		if enemy.scared {
+			goatbird_movement.feeding = false;
+			release_grabbed(&mut goatbird_movement, &mut goat_query, &mut transitions);
+		} else {
+			let beak = enemy.cart_transform - goat_middle;
 			let mut closest_goat = 99999.0;
 			let mut closest_goat_loc = Vec2::new(0.0, 800.0);
-			for (goat_entity, goat_transform, goat_movement) in goat_query.iter_mut() {
-				let distance = (goat_movement.cart_transform + iso_to_cart(Vec2::new(0.0, 16.0)) - goatbird_movement.cart_transform).length();
-				if distance < closest_goat && !goat_movement.safe {
-					closest_goat = distance;
-					closest_goat_loc = goat_movement.cart_transform + iso_to_cart(Vec2::new(0.0, 16.0));
-				}
+			let closest = spatial_index.goats.nearest(beak, |entity| goat_query.get(entity).is_ok_and(|(_, _, goat_movement)| goat_movement.in_play()));
+			if let Some((_, _, goat_movement)) = closest.and_then(|(entity, _)| goat_query.get(entity).ok()) {
+				closest_goat = (goat_movement.cart_transform + goat_middle - enemy.cart_transform).length();
+				closest_goat_loc = goat_movement.cart_transform + goat_middle;
 			}
-			goatbird_movement.velocity = (closest_goat_loc - goatbird_movement.cart_transform).normalize_or_zero();
+			enemy.velocity = (closest_goat_loc - enemy.cart_transform).normalize_or_zero();
 			if closest_goat < 16.0 {
 				goatbird_movement.feeding = true;
 				goatbird_movement.feed_timer.tick(time.delta());
-				for (goat_entity, goat_transform, mut goat_movement) in goat_query.iter_mut() {
-					if (goat_movement.cart_transform + iso_to_cart(Vec2::new(0.0, 16.0)) - goatbird_movement.cart_transform).length() < 16.0 {
-						goat_movement.grabbed = true;
+				for (goat_entity, _) in spatial_index.goats.nearby(beak, 16.0) {
+					let Ok((_, _, mut goat_movement)) = goat_query.get_mut(goat_entity) else {continue};
+					let state = goat_movement.state();
+					if (goat_movement.cart_transform + goat_middle - enemy.cart_transform).length() < 16.0 && state != GoatState::Grabbed && goat_movement.in_play() {
+						goat_movement.set_state(goat_entity, GoatState::Grabbed, &mut transitions);
+						goatbird_movement.grabbed.push(goat_entity);
 					}
 				}
 				if goatbird_movement.feed_timer.just_finished() {
 					goatbird_movement.feeding = false;
-					for (goat_entity, goat_transform, mut goat_movement) in goat_query.iter_mut() {
-						let distance = (goat_movement.cart_transform + iso_to_cart(Vec2::new(0.0, 16.0)) - goatbird_movement.cart_transform).length();
-						if distance < 16.0 && !goat_movement.safe {
-							commands.entity(goat_entity).despawn_recursive();
-							goatbird_movement.scared = true;
-							goatbird_movement.velocity = goatbird_movement.cart_transform.normalize();
-							let corpse = commands.spawn((SpriteBundle {
-								transform: Transform::from_xyz(0.0, -16.0, -1.0),
-								texture: asset_server.load("goat.png"),
-								sprite: Sprite {
-									color: Color::GRAY,
-									flip_x: goatbird_movement.velocity.x > 0.0,
-									custom_size: Some(Vec2::new(64.0, 64.0)),
-									..default()
-								},
-								..default()
-								},
-							)).id();
-							commands.entity(goatbird_entity).add_child(corpse);
-							goat_numbers.killed += 1;
+					for (goat_entity, _) in spatial_index.goats.nearby(beak, 16.0) {
+						let Ok((_, _, goat_movement)) = goat_query.get(goat_entity) else {continue};
+						let distance = (goat_movement.cart_transform + goat_middle - enemy.cart_transform).length();
+						if distance < 16.0 && goat_movement.in_play() {
+							enemy.scared = true;
+							enemy.velocity = enemy.cart_transform.normalize();
+							goatbird_movement.carrying = Some(goat_entity);
+							goatbird_movement.drop_timer.reset();
 							break;
 						}
 					}
+					release_grabbed(&mut goatbird_movement, &mut goat_query, &mut transitions);
 				}
 			} else {
 				goatbird_movement.feeding = false;
 				goatbird_movement.feed_timer.reset();
+				release_grabbed(&mut goatbird_movement, &mut goat_query, &mut transitions);
 			}
-		} 
-		let goatbird_vel = cart_to_iso(goatbird_movement.velocity);
+		}
+		let goatbird_vel = cart_to_iso(enemy.velocity);
 		if !goatbird_movement.feeding {
 			goatbird_transform.translation.x = goatbird_transform.translation.x + goatbird_vel.x * goatbird_movement.speed * time.delta_seconds();
 			goatbird_transform.translation.y = goatbird_transform.translation.y + goatbird_vel.y * goatbird_movement.speed * time.delta_seconds();
 		}
-		goatbird_movement.cart_transform = iso_to_cart(goatbird_transform.translation.xy());
-		if goatbird_movement.scared {
+		enemy.cart_transform = iso_to_cart(goatbird_transform.translation.xy());
+		// Carried goats hang under the bird, and are only lost once they're off the screen
+		if let Some(goat_entity) = goatbird_movement.carrying {
+			if let Ok((_, mut goat_transform, mut goat_movement)) = goat_query.get_mut(goat_entity) {
+				goat_transform.translation.x = goatbird_transform.translation.x;
+				goat_transform.translation.y = goatbird_transform.translation.y - 16.0;
+				goat_movement.cart_transform = iso_to_cart(goat_transform.translation.xy());
+				if goat_transform.translation.x.abs() > ORTHO.x / 2.0 + 32.0
+				|| goat_transform.translation.y.abs() > ORTHO.y / 2.0 + 32.0 {
+					losses.lose(goat_entity, goat_movement.cart_transform, LossCause::Carried);
+					goatbird_movement.carrying = None;
+				}
+			}
+		}
+		if enemy.scared {
 			if goatbird_transform.translation.x.abs() > ORTHO.x + 64.0
 			&& goatbird_transform.translation.y.abs() > ORTHO.y + 64.0 {
-				commands.entity(goatbird_entity).despawn_recursive();
+				release_grabbed(&mut goatbird_movement, &mut goat_query, &mut transitions);
+				losses.commands.entity(goatbird_entity).despawn_recursive();
 			}
 		}
 	}
 }
 
-#[derive(Component)]
-pub struct Spit{
-	pub scared: bool,
-	pub velocity: Vec2,
-	pub cart_transform: Vec2,
+fn spitter_target<'a>(
+	target: SpitterTarget,
+	from: Vec2,
+	goat_query: &'a Query<(Entity, &GoatMovement)>,
+	spatial_index: &SpatialIndex,
+	pens: &Pens,
+) -> Option<&'a GoatMovement> {
+	let in_play = |entity: Entity| goat_query.get(entity).is_ok_and(|(_, goat)| goat.in_play());
+	if target == SpitterTarget::Nearest {
+		return spatial_index.goats.nearest(from, in_play).and_then(|(entity, _)| goat_query.get(entity).ok()).map(|(_, goat)| goat);
+	}
+	let open_pens: Vec<Vec2> = pens.0.iter()
+		.filter(|pen| !pen.is_full())
+		.map(|pen| pen.pen.shape.center())
+		.collect();
+	// Lowest herdmate count wins, then the shortest distance
+	let score = |entity: Entity, goat: &GoatMovement| match target {
+		SpitterTarget::Nearest => (0, goat.cart_transform.distance(from)),
+		SpitterTarget::Isolated => {
+			let herdmates = spatial_index.goats.nearby(goat.cart_transform, ISOLATION_RADIUS)
+				.filter(|&(other, point)| other != entity && point.distance(goat.cart_transform) < ISOLATION_RADIUS && in_play(other))
+				.count();
+			(herdmates, goat.cart_transform.distance(from))
+		},
+		SpitterTarget::NearestPen => (0, open_pens.iter()
+			.map(|&pen| goat.cart_transform.distance(pen))
+			.reduce(f32::min)
+			.unwrap_or_else(|| goat.cart_transform.distance(from))),
+	};
+	goat_query.iter()
+		.filter(|(_, goat)| goat.in_play())
+		.map(|(entity, goat)| (score(entity, goat), goat))
+		.min_by(|(a, _), (b, _)| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+		.map(|(_, goat)| goat)
 }
 
-#[derive(Component)]
-pub struct Arm{
-	pub wait_timer: Timer,
-	pub slam_timer: Timer,
+// Where to fire so a spit at this speed meets a goat keeping to its velocity,
+// or just at the goat when it can't be caught
+fn lead_aim(
+	from: Vec2,
+	target: Vec2,
+	velocity: Vec2,
+	speed: f32,
+) -> Vec2 {
+	// Smallest t > 0 with |offset + velocity * t| = speed * t
+	let offset = target - from;
+	let a = velocity.length_squared() - speed * speed;
+	let b = 2.0 * offset.dot(velocity);
+	let c = offset.length_squared();
+	let t = if a.abs() < f32::EPSILON {
+		if b < 0.0 {Some(-c / b)} else {None}
+	} else {
+		let discriminant = b * b - 4.0 * a * c;
+		(discriminant >= 0.0).then(|| {
+			let root = discriminant.sqrt();
+			let (near, far) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+			if near.min(far) > 0.0 {near.min(far)} else {near.max(far)}
+		}).filter(|&t| t > 0.0)
+	};
+	target + velocity * t.unwrap_or(0.0)
 }
 
 fn spitter_stuff(
-	mut commands: Commands,
 	time: Res<Time>,
-	mut spitter_query: Query<(&mut Spitter)>,
-	mut spit_query: Query<(Entity, &mut Transform, &mut Spit)>,
+	mut spitter_query: Query<(&Enemy, &mut Spitter)>,
 	goat_query: Query<(Entity, &GoatMovement)>,
-	asset_server: Res<AssetServer>,
-	mut goat_numbers: ResMut<GoatNumbers>,
+	spatial_index: Res<SpatialIndex>,
+	pens: Res<Pens>,
+	mut game_rng: ResMut<GameRng>,
+	mut enemy_spawner: EnemySpawner,
 ) {
-	for (mut spitter) in spitter_query.iter_mut() {
+	for (enemy, mut spitter) in spitter_query.iter_mut() {
+		if enemy.stunned() {
+			continue;
+		}
 		if !spitter.charge_timer.finished() {
 			spitter.charge_timer.tick(time.delta());
 		} else {
 			spitter.spit_timer.tick(time.delta());
 			if spitter.spit_timer.just_finished() {
-				let mut target = Vec2::ZERO;
-				for (_, goat) in goat_query.iter() {
-					target = goat.cart_transform;
+				let profile = spitter.profile;
+				let Some(goat) = spitter_target(profile.target, enemy.cart_transform, &goat_query, &spatial_index, &pens) else {continue};
+				let aim = lead_aim(enemy.cart_transform, goat.cart_transform, goat.cart_velocity() * profile.lead, profile.spit_speed);
+				let theta = ((game_rng.random(RngStream::Enemies) * 2.0 - 1.0) * profile.spread).to_radians();
+				let Some(direction) = (aim - enemy.cart_transform).try_normalize() else {continue};
+				enemy_spawner.spawn(EnemyKind::Spit, enemy.cart_transform, direction.rotate(Vec2::from_angle(theta)) * profile.spit_speed);
+				spitter.shots += 1;
+				if profile.burst.is_some_and(|burst| spitter.shots >= burst) {
+					spitter.shots = 0;
+					spitter.charge_timer.reset();
+					spitter.spit_timer.reset();
 				}
-				let theta = ((rand::random::<f32>() - 0.5) * 10.0).to_radians();
-				let iso_loc = cart_to_iso(spitter.cart_transform);
-				commands.spawn((SpriteBundle {
-					transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 800.0),
-					texture: asset_server.load("spit.png"),
-					sprite: Sprite {
-						color: Color::RED,
-						custom_size: Some(Vec2::new(32.0, 32.0)),
-						..default()
-					},
-					..default()
-					},
-					Spit{
-						scared: false,
-						velocity: (target-spitter.cart_transform).normalize().rotate(Vec2::from_angle(theta)),
-						cart_transform: spitter.cart_transform
-					},
-				));
 			}
 		}
 	}
+}
+
+fn spit_stuff(
+	time: Res<Time>,
+	mut spit_query: Query<(Entity, &mut Transform, &mut Enemy), With<Spit>>,
+	goat_query: Query<(Entity, &GoatMovement)>,
+	mut losses: GoatLosses,
+	spatial_index: Res<SpatialIndex>,
+) {
 	for (spit_entity, mut transform, mut spit) in spit_query.iter_mut() {
-		for (goat_entity, goat) in goat_query.iter() {
-			if (goat.cart_transform - iso_to_cart(transform.translation.xy())).length() < 24.0 {
-				commands.entity(goat_entity).despawn_recursive();
-				commands.entity(spit_entity).despawn_recursive();
-				goat_numbers.killed += 1;
+		let spit_loc = iso_to_cart(transform.translation.xy());
+		for (goat_entity, _) in spatial_index.goats.nearby(spit_loc, 24.0) {
+			let Ok((_, goat)) = goat_query.get(goat_entity) else {continue};
+			if goat.in_play() && (goat.cart_transform - spit_loc).length() < 24.0 {
+				losses.lose(goat_entity, goat.cart_transform, LossCause::Spat);
+				losses.commands.entity(spit_entity).despawn_recursive();
 				break;
 			}
 		}
-		let dir = cart_to_iso(spit.velocity);
-		transform.translation.x += dir.x * 300.0 * time.delta_seconds();
-		transform.translation.y += dir.y * 300.0 * time.delta_seconds();
+		let iso_velocity = cart_to_iso(spit.velocity);
+		transform.translation.x += iso_velocity.x * time.delta_seconds();
+		transform.translation.y += iso_velocity.y * time.delta_seconds();
 		spit.cart_transform = iso_to_cart(transform.translation.xy());
 		if transform.translation.x.abs() > 1000.0
 		|| transform.translation.y.abs() > 600.0 {
-			commands.entity(spit_entity).despawn_recursive();
+			losses.commands.entity(spit_entity).despawn_recursive();
 		}
 	}
 }
 
+// Iso screen size of the box around a shape
+fn iso_extent(
+	shape: &LandShape,
+) -> Vec2 {
+	let (min, max) = shape.points().iter()
+		.map(|&point| cart_to_iso(point))
+		.fold((Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)), |(min, max), point| (min.min(point), max.max(point)));
+	max - min
+}
+
+// Shadow sprite an arm casts on its zone before a slam
+type ShadowArt<'a> = (&'a mut Transform, &'a mut Sprite, &'a mut Visibility);
+
+const SHAKE_SECONDS: f32 = 0.4;
+const SHAKE_STRENGTH: f32 = 12.0;
+
 fn arm_stuff(
-	mut commands: Commands,
 	time: Res<Time>,
-	mut arm_query: Query<(Entity, &mut Transform, &mut TextureAtlas, &mut Arm)>,
+	mut arm_query: Query<(&mut Enemy, &mut Transform, &mut TextureAtlas, &mut Arm, &Children)>,
+	mut shadow_query: Query<ShadowArt, (With<ArmShadow>, Without<Arm>)>,
 	goat_query: Query<(Entity, &GoatMovement)>,
-	mut goat_numbers: ResMut<GoatNumbers>,
+	mut losses: GoatLosses,
+	mut screen_shake: ResMut<ScreenShake>,
 ) {
-	for (entity, mut transform, mut atlas, mut arm) in arm_query.iter_mut() {
+	for (mut enemy, mut transform, mut atlas, mut arm, children) in arm_query.iter_mut() {
+		if enemy.stunned() {
+			continue;
+		}
+		let Some(zone) = arm.attack.zones.get(arm.zone).cloned() else {continue};
 		if !arm.wait_timer.finished() {
+			// The raised arm hangs over the zone it's about to slam
+			let art_offset = cart_to_iso(zone.center() - ARM_ART_ZONE);
+			transform.translation.x = art_offset.x;
+			transform.translation.y = art_offset.y;
 			arm.wait_timer.tick(time.delta());
 			if arm.wait_timer.just_finished() {
 				arm.slam_timer.reset();
 				atlas.index = 0;
 				transform.translation.z = 553.0;
 				for (entity, goat) in goat_query.iter() {
-					if (goat.cart_transform.x - 150.0*REVERT).abs() < 100.0*REVERT
-					&& (goat.cart_transform.y - 300.0*REVERT).abs() < 150.0*REVERT {
-						commands.entity(entity).despawn_recursive();
-						goat_numbers.killed += 1;
+					if goat.in_play() && zone.contains(goat.cart_transform) {
+						losses.lose(entity, goat.cart_transform, LossCause::Slammed);
 					}
 				}
+				screen_shake.start(SHAKE_SECONDS, SHAKE_STRENGTH);
+				arm.slams += 1;
+				arm.zone = (arm.zone + 1) % arm.attack.zones.len();
 			}
 		} else if !arm.slam_timer.finished() {
 			arm.slam_timer.tick(time.delta());
 			if arm.slam_timer.just_finished() {
-				arm.wait_timer.reset();
+				arm.wait_timer = Timer::from_seconds(arm.attack.wait, TimerMode::Once);
 				atlas.index = 1;
 				transform.translation.z = 553.0;
+				if arm.attack.count.is_some_and(|count| arm.slams >= count) {
+					enemy.health = 0.0;
+				}
 			}
 		}
+		enemy.cart_transform = zone.center();
+
+		// The shadow grows over the zone through the last telegraph seconds of the wait
+		let grown = if arm.wait_timer.finished() {
+			0.0
+		} else {
+			(1.0 - arm.wait_timer.remaining_secs() / arm.attack.telegraph.max(f32::EPSILON)).clamp(0.0, 1.0)
+		};
+		for &child in children.iter() {
+			let Ok((mut shadow_transform, mut sprite, mut visibility)) = shadow_query.get_mut(child) else {continue};
+			let shadow_loc = cart_to_iso(zone.center()) - transform.translation.xy();
+			shadow_transform.translation.x = shadow_loc.x;
+			shadow_transform.translation.y = shadow_loc.y;
+			sprite.custom_size = Some(iso_extent(&zone) * grown);
+			sprite.color = Color::rgba(1.0, 1.0, 1.0, 0.8 * grown);
+			*visibility = if grown > 0.0 {Visibility::Inherited} else {Visibility::Hidden};
+		}
+	}
+}
+
+fn shake_camera(
+	time: Res<Time>,
+	mut screen_shake: ResMut<ScreenShake>,
+	mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+	if screen_shake.timer.finished() {
+		return;
+	}
+	screen_shake.timer.tick(time.delta());
+	// Dies away to nothing, which also puts the camera back where it was
+	let strength = screen_shake.strength * (1.0 - screen_shake.timer.fraction());
+	let t = screen_shake.timer.elapsed_secs();
+	for mut transform in camera_query.iter_mut() {
+		transform.translation.x = (t * 73.0).sin() * strength;
+		transform.translation.y = (t * 91.0).cos() * strength;
 	}
-}
\ No newline at end of file
+}
+
+fn burrower_stuff(
+	time: Res<Time>,
+	mut burrower_query: Query<(&mut Enemy, &mut Transform, &mut Sprite, &mut Burrower, &Children)>,
+	mut body_query: Query<&mut Visibility, With<BurrowerBody>>,
+	mut goat_query: Query<(Entity, &mut GoatMovement)>,
+	mut losses: GoatLosses,
+	spatial_index: Res<SpatialIndex>,
+	mut transitions: EventWriter<GoatTransition>,
+) {
+	// Mounds go by where goats stand rather than their middle
+	let goat_feet = iso_to_cart(Vec2::new(0.0, 32.0));
+	for (mut enemy, mut transform, mut sprite, mut burrower, children) in burrower_query.iter_mut() {
+		if enemy.stunned() {
+			continue;
+		}
+		let mound = enemy.cart_transform;
+		let free_goat = |goat: &GoatMovement, radius: f32| goat.in_play() && goat.state() != GoatState::Grabbed && (goat.cart_transform - goat_feet).distance(mound) < radius;
+		burrower.timer.tick(time.delta());
+
+		if enemy.scared && burrower.state != BurrowState::Surfaced {
+			burrower.state = BurrowState::Surfaced;
+			burrower.timer = Timer::from_seconds(HOLD_SECONDS, TimerMode::Once);
+		}
+		// Let go of a goat it was flushed out holding
+		if enemy.scared {
+			if let Some((goat_entity, mut goat_movement)) = burrower.prey.take().and_then(|goat| goat_query.get_mut(goat).ok()) {
+				if goat_movement.state() == GoatState::Grabbed {
+					goat_movement.velocity = (goat_movement.cart_transform - mound).try_normalize().unwrap_or(Vec2::X);
+					goat_movement.set_state(goat_entity, GoatState::Panicked{fear: 1.0}, &mut transitions);
+				}
+			}
+		}
+
+		match burrower.state {
+			BurrowState::Digging => {
+				if burrower.timer.finished() {
+					burrower.state = BurrowState::Lurking;
+					burrower.timer = Timer::from_seconds(LURK_SECONDS, TimerMode::Once);
+				}
+			},
+			BurrowState::Lurking => {
+				// Nothing came by, so it goes back under without surfacing
+				if burrower.timer.finished() {
+					enemy.health = 0.0;
+					continue;
+				}
+				let near = spatial_index.goats.nearby(mound, AMBUSH_RADIUS + GRAB_RADIUS)
+					.any(|(goat_entity, _)| goat_query.get(goat_entity).is_ok_and(|(_, goat)| free_goat(goat, AMBUSH_RADIUS)));
+				if near {
+					burrower.state = BurrowState::Erupting;
+					burrower.timer = Timer::from_seconds(ERUPT_SECONDS, TimerMode::Once);
+				}
+			},
+			BurrowState::Erupting => {
+				if burrower.timer.finished() {
+					let prey = spatial_index.goats.nearby(mound, GRAB_RADIUS + 32.0)
+						.filter_map(|(goat_entity, _)| goat_query.get(goat_entity).ok())
+						.filter(|(_, goat)| free_goat(goat, GRAB_RADIUS))
+						.min_by(|(_, a), (_, b)| a.cart_transform.distance(mound).total_cmp(&b.cart_transform.distance(mound)))
+						.map(|(goat_entity, _)| goat_entity);
+					if let Some((goat_entity, mut goat_movement)) = prey.and_then(|goat| goat_query.get_mut(goat).ok()) {
+						goat_movement.set_state(goat_entity, GoatState::Grabbed, &mut transitions);
+						burrower.prey = Some(goat_entity);
+					}
+					burrower.state = BurrowState::Surfaced;
+					burrower.timer = Timer::from_seconds(HOLD_SECONDS, TimerMode::Once);
+				}
+			},
+			BurrowState::Surfaced => {
+				if burrower.timer.finished() {
+					if let Some((goat_entity, goat_movement)) = burrower.prey.take().and_then(|goat| goat_query.get(goat).ok()) {
+						if goat_movement.state() == GoatState::Grabbed {
+							losses.lose(goat_entity, goat_movement.cart_transform, LossCause::Ambushed);
+						}
+					}
+					// Back under and off somewhere else
+					enemy.health = 0.0;
+				}
+			},
+		}
+
+		let grown = match burrower.state {
+			BurrowState::Digging => burrower.timer.fraction(),
+			_ => 1.0,
+		};
+		sprite.custom_size = Some(MOUND_SIZE * grown);
+		// The mound shakes harder the closer it is to erupting
+		let iso_loc = cart_to_iso(mound);
+		let shake = match burrower.state {
+			BurrowState::Erupting => (burrower.timer.elapsed_secs() * 60.0).sin() * 4.0 * burrower.timer.fraction(),
+			_ => 0.0,
+		};
+		transform.translation.x = iso_loc.x + shake;
+		transform.translation.y = iso_loc.y;
+		for &child in children.iter() {
+			let Ok(mut visibility) = body_query.get_mut(child) else {continue};
+			*visibility = if burrower.state == BurrowState::Surfaced {Visibility::Inherited} else {Visibility::Hidden};
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use bevy::ecs::system::RunSystemOnce;
+
+	use crate::{goat::Breed, spatial::SpatialHash};
+
+	use super::*;
+
+	// A goatbird at cart (100, 0) with goats under its beak, offset from the first one
+	fn feeding_world(
+		offsets: &[Vec2],
+	) -> (World, Entity, Vec<Entity>) {
+		let mut world = World::new();
+		world.init_resource::<Time>();
+		world.init_resource::<Events<Scare>>();
+		world.init_resource::<Events<GoatTransition>>();
+		world.insert_resource(GoatNumbers{spawned: 0, killed: 0, total: 0, extra: 0, rescued: 0, losses: default()});
+		let mut spatial_index = SpatialIndex{goats: SpatialHash::new(64.0), enemies: SpatialHash::new(64.0)};
+		let bird_loc = Vec2::new(100.0, 0.0);
+		let beak = bird_loc - iso_to_cart(Vec2::new(0.0, 16.0));
+		let goats = offsets.iter().map(|&offset| {
+			let goat = world.spawn((Transform::default(), GoatMovement::new(Breed::Plain, beak + offset, Vec2::X))).id();
+			spatial_index.goats.insert(goat, beak + offset);
+			goat
+		}).collect();
+		world.insert_resource(spatial_index);
+		let iso_loc = cart_to_iso(bird_loc);
+		let bird = world.spawn((
+			Transform::from_xyz(iso_loc.x, iso_loc.y, 800.0),
+			Enemy::new(EnemyKind::Goatbird, bird_loc, Vec2::X),
+			GoatbirdMovement{
+				speed: 110.0,
+				feeding: false,
+				feed_timer: Timer::from_seconds(3.0, TimerMode::Once),
+				carrying: None,
+				grabbed: Vec::new(),
+				drop_timer: Timer::from_seconds(DROP_WINDOW, TimerMode::Once),
+			},
+		)).id();
+		(world, bird, goats)
+	}
+
+	// Upkeep first, as in the game, which also lets the new bird's stun run out
+	fn step(
+		world: &mut World,
+	) {
+		world.run_system_once(enemy_upkeep);
+		world.run_system_once(goatbird_movement);
+	}
+
+	fn goat_state(
+		world: &World,
+		goat: Entity,
+	) -> GoatState {
+		world.get::<GoatMovement>(goat).unwrap().state()
+	}
+
+	#[test]
+	fn scared_mid_feed_lets_go() {
+		let (mut world, bird, goats) = feeding_world(&[Vec2::ZERO, Vec2::new(4.0, 0.0)]);
+		step(&mut world);
+		assert!(goats.iter().all(|&goat| goat_state(&world, goat) == GoatState::Grabbed));
+		world.get_mut::<Enemy>(bird).unwrap().scared = true;
+		step(&mut world);
+		assert!(goats.iter().all(|&goat| goat_state(&world, goat) == GoatState::Stunned));
+		assert!(world.get::<GoatbirdMovement>(bird).unwrap().grabbed.is_empty());
+	}
+
+	#[test]
+	fn only_the_carried_goat_stays_grabbed() {
+		let (mut world, bird, goats) = feeding_world(&[Vec2::ZERO, Vec2::new(4.0, 0.0)]);
+		world.resource_mut::<Time>().advance_by(Duration::from_secs_f32(3.1));
+		step(&mut world);
+		let carrying = world.get::<GoatbirdMovement>(bird).unwrap().carrying.expect("carrying a goat");
+		for goat in goats {
+			let expected = if goat == carrying {GoatState::Grabbed} else {GoatState::Stunned};
+			assert_eq!(goat_state(&world, goat), expected);
+		}
+	}
+
+	#[test]
+	fn lead_aim_meets_the_goat() {
+		let aim = lead_aim(Vec2::ZERO, Vec2::new(100.0, 0.0), Vec2::new(0.0, 100.0), 300.0);
+		assert!(aim.distance(Vec2::new(100.0, 100.0 / 8.0_f32.sqrt())) < 0.01, "{}", aim);
+		// The spit and the goat get there at the same time
+		let from = Vec2::new(-50.0, 20.0);
+		let (target, velocity, speed) = (Vec2::new(200.0, -80.0), Vec2::new(-60.0, 90.0), 250.0);
+		let aim = lead_aim(from, target, velocity, speed);
+		assert!((aim.distance(from) / speed - aim.distance(target) / velocity.length()).abs() < 0.001);
+	}
+
+	#[test]
+	fn lead_aim_without_a_lead() {
+		let target = Vec2::new(100.0, 50.0);
+		assert_eq!(lead_aim(Vec2::ZERO, target, Vec2::ZERO, 300.0), target);
+		// Running away faster than a spit flies
+		assert_eq!(lead_aim(Vec2::ZERO, target, target.normalize() * 400.0, 300.0), target);
+		// Running away exactly as fast
+		assert_eq!(lead_aim(Vec2::ZERO, target, target.normalize() * 300.0, 300.0), target);
+	}
+
+	#[test]
+	fn lead_aim_same_speed_coming_closer() {
+		// Goat walks straight at the spitter as fast as a spit, they meet halfway
+		let aim = lead_aim(Vec2::ZERO, Vec2::new(100.0, 0.0), Vec2::new(-300.0, 0.0), 300.0);
+		assert!(aim.distance(Vec2::new(50.0, 0.0)) < 0.01, "{}", aim);
+	}
+}
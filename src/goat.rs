@@ -1,10 +1,12 @@
-use std::time::Duration;
+use std::mem::discriminant;
 
 // Goat module, for handling goat movement and interactions
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 use bevy_kira_audio::{AudioControl, Audio};
+use rand::{distributions::{Distribution, WeightedIndex}, Rng};
+use serde::{Deserialize, Serialize};
 
-use crate::{enemy::{Arm, GoatbirdMovement, Spitter}, helper::{cart_to_iso, iso_to_cart, GameState}, land::{Anchors, Goal, Land}, menu::SFX_SCALING, setup::{GameMode, ProgressTracker, Volume, ORTHO, REVERT}};
+use crate::{elevation::MAX_STEP, helper::{cart_to_iso, iso_to_cart, GameState}, land::{Anchors, Ground, Pens}, level::CurrentLevel, menu::SFX_SCALING, rng::{GameRng, RngStream}, setup::Volume, spatial::{SpatialHash, SpatialIndex}};
 
 pub struct GoatPlugin;
 
@@ -14,53 +16,371 @@ impl Plugin for GoatPlugin {
 			.insert_resource(GoatNumbers{
 				spawned: 0,
 				killed: 0,
+				losses: HashMap::new(),
 				total: 0,
+				extra: 0,
+				rescued: 0,
 			})
-			.add_systems(OnEnter(GameState::Game), (
-				goat_setup,
+			.insert_resource(Flocking{
+				radius: 120.0,
+				separation_radius: 40.0,
+				separation: 1.5,
+				alignment: 1.0,
+				cohesion: 0.6,
+				turn_rate: 3.0,
+			})
+			.add_event::<GoatTransition>()
+			.add_event::<Scare>()
+			.init_resource::<SpreadingScares>()
+			.add_systems(OnExit(GameState::Game), (
+				clear_spreading,
 			))
 			.add_systems(Update, (
 				goat_movement,
-				spawn_goat_wave,
+				spread_panic.before(goat_movement),
+				goat_state_tint.after(goat_movement),
+				goat_fall.after(goat_movement),
+				goat_fall_bleat.after(goat_movement),
 			).run_if(in_state(GameState::Game)))
 		;
 	}
 }
 
-const GOAT_SPAWN_ORDER: [f32; 32] = [
-	1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 
-	0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0,
-	0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0,
-	0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0,
-];
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Breed {
+	#[default]
+	Plain,
+	// Small and quick, wanders a lot
+	Kid,
+	// Stubborn, barely listens to bells
+	Billy,
+	// Skittish, hears bells from further and bolts
+	Nanny,
+	// Slow and heavy, other goats make way for it
+	Ram,
+}
+
+impl Breed {
+	pub const ALL: [Breed; 5] = [Breed::Plain, Breed::Kid, Breed::Billy, Breed::Nanny, Breed::Ram];
+
+	pub fn profile(
+		&self,
+	) -> BreedProfile {
+		match self {
+			Breed::Plain => BreedProfile{speed: 90.0, boost_speed: 200.0, move_seconds: 3.0, idle_seconds: 1.0, bell_sensitivity: 1.0, wander: 1.0, mass: 1.0, tint: Color::WHITE, size: 64.0, weight: 4.0},
+			Breed::Kid => BreedProfile{speed: 110.0, boost_speed: 220.0, move_seconds: 1.5, idle_seconds: 0.5, bell_sensitivity: 1.2, wander: 1.6, mass: 0.5, tint: Color::rgb(1.0, 0.95, 0.85), size: 48.0, weight: 2.0},
+			Breed::Billy => BreedProfile{speed: 80.0, boost_speed: 160.0, move_seconds: 3.5, idle_seconds: 1.5, bell_sensitivity: 0.5, wander: 0.6, mass: 1.3, tint: Color::rgb(0.75, 0.65, 0.55), size: 64.0, weight: 1.0},
+			Breed::Nanny => BreedProfile{speed: 95.0, boost_speed: 240.0, move_seconds: 2.5, idle_seconds: 0.8, bell_sensitivity: 1.6, wander: 1.3, mass: 1.0, tint: Color::rgb(1.0, 0.88, 0.9), size: 60.0, weight: 2.0},
+			Breed::Ram => BreedProfile{speed: 65.0, boost_speed: 150.0, move_seconds: 4.0, idle_seconds: 1.2, bell_sensitivity: 0.8, wander: 0.5, mass: 2.0, tint: Color::rgb(0.6, 0.6, 0.65), size: 80.0, weight: 1.0},
+		}
+	}
+
+	// Furthest any breed hears a bell, as a multiple of the bell's range
+	pub fn max_bell_sensitivity() -> f32 {
+		Breed::ALL.iter().map(|breed| breed.profile().bell_sensitivity).fold(0.0, f32::max)
+	}
+}
+
+pub struct BreedProfile{
+	pub speed: f32,
+	pub boost_speed: f32,
+	pub move_seconds: f32,
+	pub idle_seconds: f32,
+	// Scales how far away a goat hears bells, and how far stubborn ones turn for them
+	pub bell_sensitivity: f32,
+	// Scales how sharply a goat turns between walks
+	pub wander: f32,
+	// Lighter goats give way to heavier ones when flocking
+	pub mass: f32,
+	pub tint: Color,
+	pub size: f32,
+	// Spawn weight on generated maps, levels set their own
+	pub weight: f32,
+}
+
+// Level spawn table entry, goats are picked in proportion to weight
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct BreedWeight{
+	pub breed: Breed,
+	pub weight: f32,
+}
+
+// Plain goats only when the table is empty
+pub fn pick_breed(
+	table: &[BreedWeight],
+	rng: &mut impl Rng,
+) -> Breed {
+	let Ok(index) = WeightedIndex::new(table.iter().map(|entry| entry.weight)) else {return Breed::Plain};
+	table[index.sample(rng)].breed
+}
+
+pub fn spawn_goat(
+	commands: &mut Commands,
+	asset_server: &AssetServer,
+	breed: Breed,
+	cart_loc: Vec2,
+	facing: Vec2,
+) -> Entity {
+	let profile = breed.profile();
+	let iso_loc = cart_to_iso(cart_loc);
+	commands.spawn((SpriteBundle {
+		transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 600.0),
+		texture: asset_server.load("goat.png"),
+		sprite: Sprite {
+			color: profile.tint,
+			custom_size: Some(Vec2::splat(profile.size)),
+			..default()
+		},
+		..default()
+		},
+		GoatMovement::new(breed, cart_loc, facing),
+	)).id()
+}
+
+// What a goat is up to. Only changed through GoatMovement::set_state, so
+// every change of state sends a GoatTransition.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GoatState {
+	// Walking its own way, bent by the flock
+	Wandering,
+	// Standing about between walks
+	Idle,
+	// Running at boost speed after the boost bell
+	Boosted,
+	// Held by a goatbird
+	Grabbed,
+	// Walking straight where a bell sent it
+	Fleeing,
+	// Bolting from something frightening, deaf to bells until it calms down
+	Panicked{fear: f32},
+	// Stunned after a drop
+	Stunned,
+	// Gone over the edge, lost once the fall plays out
+	Falling,
+	// Herded for good, still ambling about inside the pen
+	Penned{pen: usize, resting: bool},
+}
+
+const BOOST_SECONDS: f32 = 3.0;
+const STUN_SECONDS: f32 = 1.0;
+const FALL_SECONDS: f32 = 1.0;
+// How long a goat panics at full fear, less fear calms down sooner
+const PANIC_SECONDS: f32 = 3.0;
+// Goats this close to a death panic, and each panicking goat frightens goats
+// within SPREAD_RADIUS a little less, until fear drops under MIN_FEAR
+const FEAR_RADIUS: f32 = 250.0;
+const SPREAD_RADIUS: f32 = 90.0;
+const SPREAD_FALLOFF: f32 = 0.6;
+const MIN_FEAR: f32 = 0.2;
+// Iso pixels per second squared, goats are well out of sight by the end of a fall
+const FALL_GRAVITY: f32 = 900.0;
+
+// Something frightening happened here, fear falls off with distance
+#[derive(Event, Clone, Copy)]
+pub struct Scare{
+	pub location: Vec2,
+	pub radius: f32,
+	pub fear: f32,
+}
+
+impl Scare {
+	// A goat killed at this cart position
+	pub fn death(
+		location: Vec2,
+	) -> Scare {
+		Scare{location, radius: FEAR_RADIUS, fear: 1.0}
+	}
+}
+
+// Panic passed on by frightened goats, handled the frame after
+#[derive(Resource, Default)]
+struct SpreadingScares(Vec<Scare>);
 
-const GOATBIRD_SPAWN_ORDER: [f32; 32] = [
-	0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 
-	0.0, 0.0, 1.0, 2.0, 0.0, 0.0, 0.0, 0.0,
-	0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0,
-	0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
-];
+#[derive(Event)]
+pub struct GoatTransition{
+	pub goat: Entity,
+	pub from: GoatState,
+	pub to: GoatState,
+}
 
 #[derive(Component)]
 pub struct GoatMovement{
 	pub cart_transform: Vec2,
 	pub velocity: Vec2,
 	pub speed: f32,
-	pub boost_timer: Timer,
-	pub move_timer: Timer,
-	pub idle_timer: Timer,
+	state: GoatState,
+	// Runs out when the state moves on by itself
+	state_timer: Timer,
 	pub iso_mode: bool,
-	pub grabbed: bool,
-	pub safe: bool,
 	pub furthest_tile: usize,
+	pub breed: Breed,
+	// Turned back at the edge and not grounded again yet, so the slip and bleat
+	// are only rolled once for each time it walks into the edge
+	at_edge: bool,
+}
+
+impl GoatMovement {
+	// Wandering off from where it arrived
+	pub fn new(
+		breed: Breed,
+		cart_loc: Vec2,
+		facing: Vec2,
+	) -> GoatMovement {
+		let profile = breed.profile();
+		GoatMovement {
+			cart_transform: cart_loc,
+			velocity: facing,
+			speed: profile.speed,
+			state: GoatState::Wandering,
+			state_timer: Timer::from_seconds(profile.move_seconds, TimerMode::Once),
+			iso_mode: true,
+			furthest_tile: 0,
+			breed,
+			at_edge: false,
+		}
+	}
+
+	pub fn state(
+		&self,
+	) -> GoatState {
+		self.state
+	}
+
+	pub fn pen(
+		&self,
+	) -> Option<usize> {
+		match self.state {
+			GoatState::Penned{pen, ..} => Some(pen),
+			_ => None,
+		}
+	}
+
+	pub fn is_penned(
+		&self,
+	) -> bool {
+		self.pen().is_some()
+	}
+
+	// Still out on the land, where bells and enemies can get at it
+	pub fn in_play(
+		&self,
+	) -> bool {
+		!matches!(self.state, GoatState::Penned{..} | GoatState::Falling)
+	}
+
+	// Moving under its own steam this frame
+	pub fn walking(
+		&self,
+	) -> bool {
+		match self.state {
+			GoatState::Wandering | GoatState::Boosted | GoatState::Fleeing | GoatState::Panicked{..} => true,
+			GoatState::Penned{resting, ..} => !resting,
+			GoatState::Idle | GoatState::Stunned | GoatState::Falling | GoatState::Grabbed => false,
+		}
+	}
+
+	// Cart pixels per second it's covering, for anything that wants to aim ahead of it
+	pub fn cart_velocity(
+		&self,
+	) -> Vec2 {
+		if self.walking() {self.velocity * self.speed} else {Vec2::ZERO}
+	}
+
+	pub fn set_state(
+		&mut self,
+		goat: Entity,
+		state: GoatState,
+		transitions: &mut EventWriter<GoatTransition>,
+	) {
+		let profile = self.breed.profile();
+		let seconds = match state {
+			GoatState::Wandering | GoatState::Fleeing => profile.move_seconds,
+			GoatState::Idle => profile.idle_seconds,
+			GoatState::Boosted => BOOST_SECONDS,
+			GoatState::Panicked{fear} => PANIC_SECONDS * fear,
+			GoatState::Stunned => STUN_SECONDS,
+			GoatState::Falling => FALL_SECONDS,
+			GoatState::Penned{resting, ..} => if resting {profile.idle_seconds} else {profile.move_seconds},
+			GoatState::Grabbed => 0.0,
+		};
+		self.state_timer = Timer::from_seconds(seconds, TimerMode::Once);
+		// Resting and walking inside a pen isn't news to anyone
+		if discriminant(&self.state) != discriminant(&state) {
+			transitions.send(GoatTransition{goat, from: self.state, to: state});
+		}
+		self.state = state;
+	}
+
+	// Jump part of the way through the current state
+	pub fn skip_time(
+		&mut self,
+		fraction: f32,
+	) {
+		let skipped = self.state_timer.duration().mul_f32(fraction);
+		self.state_timer.tick(skipped);
+	}
 }
 
+// Herd behaviour weights. Moving goats steer away from goats that are too
+// close, towards the heading of nearby goats and towards their middle.
 #[derive(Resource)]
-pub struct GoatSpawnTimer{
-	pub goat_wave: usize,
-	pub goatbird_wave: usize,
-	pub goat_timer: Timer,
-	pub goatbird_timer: Timer,
+pub struct Flocking{
+	// Goats within this cart distance count as neighbours
+	pub radius: f32,
+	pub separation_radius: f32,
+	pub separation: f32,
+	pub alignment: f32,
+	pub cohesion: f32,
+	// How quickly steering turns a goat, per second
+	pub turn_rate: f32,
+}
+
+// What a goat's neighbours need to know about it
+struct Herdmate{
+	cart_transform: Vec2,
+	velocity: Vec2,
+	penned: bool,
+	mass: f32,
+}
+
+impl Flocking {
+	fn steering(
+		&self,
+		entity: Entity,
+		goat_movement: &GoatMovement,
+		herd: &HashMap<Entity, Herdmate>,
+		goats: &SpatialHash,
+	) -> Vec2 {
+		let mut separation = Vec2::ZERO;
+		let mut heading = Vec2::ZERO;
+		let mut middle = Vec2::ZERO;
+		let mut neighbours = 0;
+		// Penned goats only flock with each other, so they don't get pulled into the pen walls
+		let mates = goats.nearby(goat_movement.cart_transform, self.radius)
+			.filter(|(mate, _)| *mate != entity)
+			.filter_map(|(mate, _)| herd.get(&mate))
+			.filter(|mate| mate.penned == goat_movement.is_penned());
+		for mate in mates {
+			let offset = goat_movement.cart_transform - mate.cart_transform;
+			let distance = offset.length();
+			if distance >= self.radius {
+				continue;
+			}
+			if distance < self.separation_radius {
+				let give_way = mate.mass / goat_movement.breed.profile().mass;
+				separation += offset.normalize_or_zero() * (1.0 - distance / self.separation_radius) * give_way;
+			}
+			heading += mate.velocity;
+			middle += mate.cart_transform;
+			neighbours += 1;
+		}
+		if neighbours == 0 {
+			return Vec2::ZERO;
+		}
+		let alignment = heading / neighbours as f32 - goat_movement.velocity;
+		let cohesion = (middle / neighbours as f32 - goat_movement.cart_transform).normalize_or_zero();
+		separation * self.separation + alignment * self.alignment + cohesion * self.cohesion
+	}
 }
 
 #[derive(Resource)]
@@ -68,39 +388,54 @@ pub struct GoatNumbers{
 	pub spawned: usize,
 	pub killed: usize,
 	pub total: usize,
+	// Goats that arrived on top of the level's total, and how many of them were rescued
+	pub extra: usize,
+	pub rescued: usize,
+	// Goats lost so far, by what got them
+	pub losses: HashMap<LossCause, usize>,
 }
 
-fn goat_setup(
-	mut commands: Commands,
-	asset_server: Res<AssetServer>,
-) {
-	commands.insert_resource(GoatSpawnTimer{
-		goat_wave: 0,
-		goatbird_wave: 0,
-		goat_timer: Timer::from_seconds(1.2, TimerMode::Repeating),
-		goatbird_timer: Timer::from_seconds(0.9, TimerMode::Repeating),
-	});
-
-	// let iso_loc = cart_to_iso(cart_loc);
-	// commands.spawn((SpriteBundle {
-	// 	transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 600.0),
-	// 	texture: asset_server.load("goat.png"),
-	// 	sprite: Sprite {
-	// 		color: Color::BLUE,
-	// 		custom_size: Some(Vec2::new(64.0, 64.0)),
-	// 		..default()
-	// 	},
-	// 	..default()
-	// 	},
-	// 	GoatMovement {
-	// 		cart_transform: cart_loc,
-	// 		velocity: Vec2::new(0.0, -1.0),
-	// 		move_timer: Timer::from_seconds(2.0, TimerMode::Once),
-	// 		idle_timer: Timer::from_seconds(0.1, TimerMode::Once),
-	// 		iso_mode: true,
-	// 		launched: false,
-	// 	}
-	// ));
+// Listed in the loss summary in this order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LossCause {
+	// Flown off the screen by a goatbird
+	Carried,
+	Spat,
+	Slammed,
+	Fell,
+	// Off raised ground onto something far below
+	Dropped,
+	// Dragged under by a burrower
+	Ambushed,
+}
+
+impl GoatNumbers {
+	pub fn lose(
+		&mut self,
+		cause: LossCause,
+	) {
+		self.killed += 1;
+		*self.losses.entry(cause).or_default() += 1;
+	}
+
+	pub fn clear_losses(
+		&mut self,
+	) {
+		self.killed = 0;
+		self.losses.clear();
+	}
+
+	// Such as "2 fell, 1 carried", empty until a goat is lost
+	pub fn loss_summary(
+		&self,
+	) -> String {
+		let mut losses: Vec<(&LossCause, &usize)> = self.losses.iter().collect();
+		losses.sort_by_key(|(cause, _)| **cause);
+		losses.iter()
+			.map(|(cause, count)| format!("{} {}", count, format!("{:?}", cause).to_lowercase()))
+			.collect::<Vec<_>>()
+			.join(", ")
+	}
 }
 
 fn goat_movement(
@@ -108,64 +443,41 @@ fn goat_movement(
 	time: Res<Time>,
 	keyboard: Res<ButtonInput<KeyCode>>,
 	mut goat_query: Query<(Entity, &mut Transform, &mut GoatMovement)>,
-	land_query: Query<(&Transform, &Land), Without<GoatMovement>>,
-	goal_query: Query<(&Transform, &Goal), (Without<GoatMovement>, Without<Land>)>,
+	ground: Ground,
 	anchors: Res<Anchors>,
+	pens: Res<Pens>,
+	flocking: Res<Flocking>,
+	spatial_index: Res<SpatialIndex>,
+	mut transitions: EventWriter<GoatTransition>,
+	level: Res<CurrentLevel>,
 	audio: Res<Audio>,
 	volume: Res<Volume>,
 	asset_server: Res<AssetServer>,
+	mut game_rng: ResMut<GameRng>,
 ) {
-	let mut iter = goat_query.iter_combinations_mut();
-	while let Some([
-		(entity_a, mut transform_a, mut goat_movement_a),
-		(entity_b, mut transform_b, mut goat_movement_b),
-	]) = iter.fetch_next() {
-		let mut offset = goat_movement_a.cart_transform - goat_movement_b.cart_transform;
-		// Change for variable radius
-		if offset.length() <= 32.0 {
-			// let relative_velocity = goat_movement_a.velocity - goat_movement_b.velocity;
-			// // Change for variable mass
-			// let dp = offset * relative_velocity.dot(offset) / ((offset.length_squared()) * (200.0 + 200.0));
-
-			// // Change for variable mass
-			// goat_movement_a.velocity -= 2.0 * 200.0 * dp;
-			// goat_movement_b.velocity += 2.0 * 200.0 * dp;
-
-			// // Clamp velocity within range
-			// goat_movement_a.velocity = goat_movement_a.velocity.normalize_or_zero();
-			// goat_movement_b.velocity = goat_movement_b.velocity.normalize_or_zero();
-
-			// // Change for variable radius
-			// let push = (offset.normalize() * 1.01 * (32.0) - offset).extend(0.0);
-			// transform_a.translation += push;
-			// transform_b.translation -= push;
-
-			// Turn away from each other
-			//goat_movement_a.velocity = offset.normalize();
-			//goat_movement_b.velocity = -offset.normalize();
-
-			// Flip with variance
-			// let theta = (rand::random::<f32>() * 180.0 - 90.0).to_radians();
-			// Remove normalization for yeet
-			// offset = offset.normalize();
-			// Change offset to goat_movement_b.velocity for flocking
-			// goat_movement_a.velocity = Vec2::new(
-			// 	offset.x * theta.cos() - offset.y * theta.sin(),
-			// 	offset.x * theta.sin() + offset.y * theta.cos(),
-			// );
-			// goat_movement_b.velocity = -Vec2::new(
-			// 	offset.x * theta.cos() - offset.y * theta.sin(),
-			// 	offset.x * theta.sin() + offset.y * theta.cos(),
-			// );
-		}
-	}
+	let herd: HashMap<Entity, Herdmate> = goat_query.iter()
+		.filter(|(_, _, goat_movement)| !matches!(goat_movement.state(), GoatState::Grabbed | GoatState::Falling))
+		.map(|(entity, _, goat_movement)| (entity, Herdmate{
+			cart_transform: goat_movement.cart_transform,
+			velocity: goat_movement.velocity,
+			penned: goat_movement.is_penned(),
+			mass: goat_movement.breed.profile().mass,
+		}))
+		.collect();
 
+	// Goats penned so far this frame, which the pen counts don't include yet
+	let mut entering = vec![0; pens.0.len()];
 	for (entity, mut transform, mut goat_movement) in goat_query.iter_mut() {
-		goat_movement.boost_timer.tick(time.delta());
-		if goat_movement.boost_timer.finished() {
-			goat_movement.speed = 90.0;
+		// Falling goats are left to goat_fall
+		if goat_movement.state() == GoatState::Falling {
+			continue;
+		}
+		let modifier = ground.modifier_at(goat_movement.cart_transform - iso_to_cart(Vec2::new(0.0, 32.0)));
+		let profile = goat_movement.breed.profile();
+		if matches!(goat_movement.state(), GoatState::Boosted | GoatState::Panicked{..}) {
+			goat_movement.speed = profile.boost_speed * modifier.speed;
 		} else {
-			goat_movement.speed = 200.0;
+			goat_movement.speed = profile.speed * modifier.speed;
 		}
 		// goat_movement.furthest_tile = 0;
 		// for (land_transform, land) in land_query.iter() {
@@ -177,39 +489,46 @@ fn goat_movement(
 		// 	}
 		// }
 		let target = goat_movement.cart_transform + goat_movement.velocity * goat_movement.speed * time.delta_seconds() - iso_to_cart(Vec2::new(0.0, 32.0));
+		// Cliffs turn goats back, ramps and small steps don't
+		let feet = goat_movement.cart_transform - iso_to_cart(Vec2::new(0.0, 32.0));
+		if goat_movement.state() != GoatState::Grabbed && ground.height_at(target) - ground.height_at(feet) > MAX_STEP {
+			goat_movement.velocity = -goat_movement.velocity;
+		}
 		let mut grounded = false;
-		if goat_movement.safe {
-			for (goal_transform, goal) in goal_query.iter() {
-				if (target.x - goal_transform.translation.x).abs() < goal.half_size.x
-				&& (target.y - goal_transform.translation.y).abs() < goal.half_size.y {
-					grounded = true;
-					break;
-				}
-			}
+		if let Some(pen) = goat_movement.pen() {
+			grounded = ground.pen_at(target) == Some(pen);
 		} else {
-			for (land_transform, land) in land_query.iter() {
-				if (target.x - land_transform.translation.x).abs() < land.half_size.x
-				&& (target.y - land_transform.translation.y).abs() < land.half_size.y {
-					// goat_movement.cart_transform.x = goat_movement.cart_transform.x.clamp(land_transform.translation.x - land.half_size.x + 1.0, land_transform.translation.x + land.half_size.x - 1.0);
-					// goat_movement.cart_transform.y = goat_movement.cart_transform.y.clamp(land_transform.translation.y - land.half_size.y + 1.0, land_transform.translation.y + land.half_size.y - 1.0);
-					goat_movement.furthest_tile = land.tile_index.clamp(0, 5);
-					//println!("New tile: {}", goat_movement.furthest_tile);
-					grounded = true;
-				}
+			if let Some(tile_index) = ground.tile_at(target) {
+				goat_movement.furthest_tile = tile_index;
+				grounded = true;
 			}
-			for (goal_transform, goal) in goal_query.iter() {
-				if (target.x - goal_transform.translation.x).abs() < goal.half_size.x
-				&& (target.y - goal_transform.translation.y).abs() < goal.half_size.y {
-					goat_movement.safe = true;
-					break;
+			// Full pens, and pens for other breeds, are just land to walk through
+			if let Some(pen) = ground.pen_at(target) {
+				let grabbed = goat_movement.state() == GoatState::Grabbed;
+				if !grabbed && pens.0.get(pen).is_some_and(|state| state.has_room(entering[pen]) && state.pen.accepts_breed(goat_movement.breed)) {
+					entering[pen] += 1;
+					goat_movement.set_state(entity, GoatState::Penned{pen, resting: false}, &mut transitions);
 				}
 			}
 		}
 
+		// Land gone from under its feet, or a slip walking into the edge
+		let state = goat_movement.state();
+		let walking = matches!(state, GoatState::Wandering | GoatState::Boosted | GoatState::Fleeing | GoatState::Panicked{..});
+		let edge_contact = !grounded && !goat_movement.at_edge;
+		goat_movement.at_edge = !grounded;
+		if goat_movement.in_play() && state != GoatState::Grabbed {
+			let slipped = edge_contact && walking && game_rng.random(RngStream::Goats) < level.0.edge_slip;
+			if slipped || !ground.on_land(feet) {
+				goat_movement.set_state(entity, GoatState::Falling, &mut transitions);
+				continue;
+			}
+		}
+
 		if !grounded {
-			let rnd = rand::random::<f32>();
-			if rnd < 0.25 {
-				let rnd = rand::random::<f32>();
+			// Bleat when turned back at the edge, not every frame spent standing near it
+			if edge_contact && walking && game_rng.random(RngStream::Sounds) < 0.25 {
+				let rnd = game_rng.random(RngStream::Sounds);
 				let path = if rnd < 0.3 {
 					"sfx/goat_short.ogg"
 				} else if rnd < 0.6 {
@@ -235,7 +554,20 @@ fn goat_movement(
 			//goat_movement.velocity = -goat_movement.velocity;
 
 			let loc = goat_movement.cart_transform - iso_to_cart(Vec2::new(0.0, 32.0));
-			goat_movement.velocity = (anchors.0[goat_movement.furthest_tile] - loc).normalize();
+			if let Some(pen) = goat_movement.pen() {
+				// Back into its own pen, full pens drop out of the flow field
+				if let Some(state) = pens.0.get(pen) {
+					goat_movement.velocity = (state.pen.shape.center() - loc).try_normalize().unwrap_or(-goat_movement.velocity);
+				}
+			} else {
+				// The tile it strayed from may have fallen away since
+				if !ground.is_solid(goat_movement.furthest_tile) {
+					if let Some(tile_index) = ground.nearest_tile(loc) {
+						goat_movement.furthest_tile = tile_index;
+					}
+				}
+				goat_movement.velocity = (anchors.0[goat_movement.furthest_tile] - loc).normalize();
+			}
 			// println!("Furthest tile: {}", goat_movement.furthest_tile);
 			// for (land_transform, land) in land_query.iter() {
 			// 	if (loc.x - land_transform.translation.x).abs() < land.half_size.x
@@ -245,9 +577,11 @@ fn goat_movement(
 			// }
 
 			//goat_movement.move_timer.tick(Duration::from_secs(120));
-		} else {
-			// let theta = ((rand::random::<f32>() - 0.5) * 10.0).to_radians();
-			// goat_movement.velocity = goat_movement.velocity.rotate(Vec2::from_angle(theta));
+		} else if matches!(goat_movement.state(), GoatState::Wandering | GoatState::Boosted) {
+			// Flocking bends the walk rather than replacing it, so bells still turn goats
+			let steering = flocking.steering(entity, &goat_movement, &herd, &spatial_index.goats);
+			let turn = steering * flocking.turn_rate * modifier.turning * time.delta_seconds();
+			goat_movement.velocity = (goat_movement.velocity + turn).try_normalize().unwrap_or(goat_movement.velocity);
 		}
 
 		let goat_vel = if !goat_movement.iso_mode {
@@ -256,11 +590,8 @@ fn goat_movement(
 			cart_to_iso(goat_movement.velocity)
 		};
 		
-		if !goat_movement.move_timer.finished() && !goat_movement.grabbed {
-			goat_movement.move_timer.tick(time.delta());
-			if goat_movement.move_timer.just_finished() {
-				goat_movement.idle_timer.reset();
-			}
+		let state = goat_movement.state();
+		if goat_movement.walking() {
 			transform.translation.x = transform.translation.x + goat_vel.x * goat_movement.speed * time.delta_seconds();
 			transform.translation.y = transform.translation.y + goat_vel.y * goat_movement.speed * time.delta_seconds();
 			if goat_movement.iso_mode {
@@ -268,19 +599,37 @@ fn goat_movement(
 			} else {
 				goat_movement.cart_transform = transform.translation.xy();
 			}
-		} else {
-			goat_movement.idle_timer.tick(time.delta());
-			if goat_movement.idle_timer.just_finished() {
-				goat_movement.move_timer.reset();
-
-				//let theta = 30.0_f32.to_radians(); 
-				let theta = (rand::random::<f32>() * 90.0 - 45.0).to_radians();
+		} else if modifier.friction < 1.0 && state != GoatState::Grabbed {
+			// Slippery ground keeps idle goats gliding
+			let glide = goat_vel * goat_movement.speed * (1.0 - modifier.friction) * time.delta_seconds();
+			transform.translation.x += glide.x;
+			transform.translation.y += glide.y;
+			goat_movement.cart_transform = if goat_movement.iso_mode {iso_to_cart(transform.translation.xy())} else {transform.translation.xy()};
+		}
 
-				goat_movement.velocity = Vec2::new(
-					goat_movement.velocity.x * theta.cos() - goat_movement.velocity.y * theta.sin(),
-					goat_movement.velocity.x * theta.sin() + goat_movement.velocity.y * theta.cos(),
-				)
-			}
+		// Grabbed goats wait for something else to let go of them
+		if state == GoatState::Grabbed {
+			continue;
+		}
+		goat_movement.state_timer.tick(time.delta());
+		if goat_movement.state_timer.just_finished() {
+			//let theta = 30.0_f32.to_radians(); 
+			let theta = (game_rng.random(RngStream::Goats) * 90.0 - 45.0).to_radians() * modifier.turning * profile.wander;
+			let turned = goat_movement.velocity.rotate(Vec2::from_angle(theta));
+			let next = match state {
+				GoatState::Idle => {
+					goat_movement.velocity = turned;
+					GoatState::Wandering
+				},
+				GoatState::Penned{pen, resting} => {
+					if resting {
+						goat_movement.velocity = turned;
+					}
+					GoatState::Penned{pen, resting: !resting}
+				},
+				_ => GoatState::Idle,
+			};
+			goat_movement.set_state(entity, next, &mut transitions);
 		}
 		
 		// if keyboard.just_pressed(KeyCode::KeyI) && !goat_movement.iso_mode {
@@ -299,145 +648,133 @@ fn goat_movement(
 	}
 }
 
-fn spawn_goat_wave(
-	time: Res<Time>,
-	asset_server: Res<AssetServer>,
-	mut commands: Commands,
-	mut goat_spawn_timer: ResMut<GoatSpawnTimer>,
-	mut goat_numbers: ResMut<GoatNumbers>,
-	progress_tracker: Res<ProgressTracker>,
-	mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+// Stunned goats look dazed until they come round
+fn goat_state_tint(
+	mut transitions: EventReader<GoatTransition>,
+	mut goat_query: Query<(&mut Sprite, &GoatMovement)>,
 ) {
-	let speed_up = match progress_tracker.mode {
-		GameMode::Tutorial => 1.0,
-		GameMode::Campaign(i) => if i < 1 {1.0} else if i < 2 {1.5} else {2.0},
-		GameMode::Endless(i) => if i < 1 {1.0} 
-		else if i < 2 {2.0} else {3.0},
-	};
-	goat_spawn_timer.goat_timer.tick(time.delta().mul_f32(speed_up));
-	if goat_spawn_timer.goat_timer.just_finished() {
-		if GOAT_SPAWN_ORDER[goat_spawn_timer.goat_wave] == 1.0 && goat_numbers.spawned < goat_numbers.total {
-			goat_numbers.spawned += 1;
-			let cart_loc = Vec2::new(-800.0, 50.0)*REVERT;
-			let iso_loc = cart_to_iso(cart_loc);
-			commands.spawn((SpriteBundle {
-				transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 600.0),
-				texture: asset_server.load("goat.png"),
-				sprite: Sprite {
-					// color: Color::RED,
-					custom_size: Some(Vec2::new(64.0, 64.0)),
-					..default()
-				},
-				..default()
-				},
-				GoatMovement {
-					cart_transform: cart_loc,
-					velocity: Vec2::new(1.0, 0.0),
-					speed: 90.0,
-					boost_timer: Timer::from_seconds(3.0, TimerMode::Once).tick(Duration::from_secs(3)).clone(),
-					move_timer: Timer::from_seconds(3.0, TimerMode::Once),
-					idle_timer: Timer::from_seconds(1.0, TimerMode::Once),
-					iso_mode: true,
-					grabbed: false,
-					safe: false,
-					furthest_tile: 0,
-				}
-			));
+	for transition in transitions.read() {
+		let Ok((mut sprite, goat_movement)) = goat_query.get_mut(transition.goat) else {continue};
+		let tint = goat_movement.breed.profile().tint;
+		if transition.to == GoatState::Stunned {
+			sprite.color = Color::rgb(tint.r() * 0.6, tint.g() * 0.6, tint.b() * 0.6);
+		} else if transition.from == GoatState::Stunned {
+			sprite.color = tint;
 		}
-		goat_spawn_timer.goat_wave = (goat_spawn_timer.goat_wave + 1) % 32;
 	}
-	let speed_up = match progress_tracker.mode {
-		GameMode::Tutorial => 1.0,
-		GameMode::Campaign(i) => if i < 1 {1.0} else if i < 2 {1.2} else {1.4},
-		GameMode::Endless(i) => if i < 1 {1.0} 
-		else if i < 3 {1.5} else {2.0},
-	};
-	goat_spawn_timer.goatbird_timer.tick(time.delta().mul_f32(speed_up));
-	if goat_spawn_timer.goatbird_timer.just_finished() {
- 		if GOATBIRD_SPAWN_ORDER[goat_spawn_timer.goatbird_wave] == 1.0 {
-			let rand_loc = rand::random::<f32>() * 0.33;
-			let iso_loc = Vec2::new(rand_loc*960.0 + 640.0, 600.0);
-				// if rand_loc < 0.5 {Vec2::new(-500.0, 500.0)*REVERT}
-				// else if rand_loc < 0.8 {Vec2::new(-500.0, -500.0)*REVERT}
-				// else {Vec2::new(500.0, 500.0)*REVERT};
-			let cart_loc = iso_to_cart(iso_loc);
-			commands.spawn((SpriteBundle {
-				transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 800.0),
-				texture: asset_server.load("goatbird.png"),
-				sprite: Sprite {
-					//color: Color::RED,
-					custom_size: Some(Vec2::new(64.0, 64.0)),
-					..default()
-				},
-				..default()
-				},
-				GoatbirdMovement {
-					cart_transform: cart_loc,
-					velocity: Vec2::new(1.0, 0.0),
-					speed: 110.0,
-					feeding: false,
-					feed_timer: Timer::from_seconds(3.0, TimerMode::Once),
-					scared: false,
-				}
-			));
-		} else if GOATBIRD_SPAWN_ORDER[goat_spawn_timer.goatbird_wave] == 2.0 {
-			match progress_tracker.mode {
-				GameMode::Campaign(i) | GameMode::Endless(i) => {
-					if i > 0 {
-						let rnd = rand::random::<f32>();
-						let cart_loc = if rnd < 0.33 {Vec2::new(-400.0, 225.0)*REVERT}
-						else if rnd < 0.66 {Vec2::new(175.0, 50.0)*REVERT}
-						else {Vec2::new(175.0, -175.0)*REVERT};
-						let mut iso_loc = cart_to_iso(cart_loc);
-						iso_loc.y += 16.0;
-						commands.spawn((SpriteBundle {
-							transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 800.0),
-							texture: asset_server.load("spitter.png"),
-							sprite: Sprite {
-								custom_size: Some(Vec2::new(128.0, 128.0)),
-								..default()
-							},
-							..default()
-							},
-							Spitter{
-								cart_transform: iso_to_cart(iso_loc),
-								charge_timer: Timer::from_seconds(3.0, TimerMode::Once),
-								spit_timer: Timer::from_seconds(0.5, TimerMode::Repeating),
-							}
-						));
-					}
-				}
-				_ => (),
+}
+
+// Scared goats bolt straight away from the scare. Each one passes a weaker
+// scare on to its neighbours the next frame, so panic ripples out and fades.
+fn spread_panic(
+	mut scares: EventReader<Scare>,
+	mut spreading: ResMut<SpreadingScares>,
+	mut goat_query: Query<&mut GoatMovement>,
+	spatial_index: Res<SpatialIndex>,
+	mut transitions: EventWriter<GoatTransition>,
+) {
+	let current: Vec<Scare> = scares.read().copied().chain(spreading.0.drain(..)).collect();
+	for scare in current {
+		for (entity, _) in spatial_index.goats.nearby(scare.location, scare.radius) {
+			let Ok(mut goat_movement) = goat_query.get_mut(entity) else {continue};
+			if !goat_movement.in_play() || goat_movement.state() == GoatState::Grabbed {
+				continue;
+			}
+			let offset = goat_movement.cart_transform - scare.location;
+			let fear = scare.fear * (1.0 - offset.length() / scare.radius);
+			// Only a bigger fright does anything, which also stops panic echoing back and forth
+			let calmer = match goat_movement.state() {
+				GoatState::Panicked{fear: current} => current < fear,
+				_ => true,
+			};
+			if fear < MIN_FEAR || !calmer {
+				continue;
+			}
+			goat_movement.velocity = offset.try_normalize().unwrap_or(goat_movement.velocity);
+			goat_movement.set_state(entity, GoatState::Panicked{fear}, &mut transitions);
+			if fear * SPREAD_FALLOFF >= MIN_FEAR {
+				spreading.0.push(Scare{location: goat_movement.cart_transform, radius: SPREAD_RADIUS, fear: fear * SPREAD_FALLOFF});
 			}
+		}
+	}
+}
 
-		} else if GOATBIRD_SPAWN_ORDER[goat_spawn_timer.goatbird_wave] == 3.0 {
-			// match progress_tracker.mode {
-			// 	GameMode::Campaign(i) | GameMode::Endless(i) => {
-			// 		if true {//i > 2 {
-			// 			let texture_atlas_layout = texture_atlas_layouts.add(TextureAtlasLayout::from_grid(Vec2::new(1920.0, 1080.0), 2, 1, Some(Vec2::ZERO), Some(Vec2::ZERO)));
-			// 			commands.spawn((SpriteSheetBundle {
-			// 				transform: Transform::from_xyz(0.0, 0.0,  750.0),
-			// 				texture: asset_server.load("arm.png"),
-			// 				atlas: TextureAtlas{
-			// 					layout: texture_atlas_layout,
-			// 					index: 1,
-			// 				},
-			// 				sprite: Sprite {
-			// 					custom_size: Some(ORTHO),
-			// 					..default()
-			// 				},
-			// 				..default()
-			// 				},
-			// 				Arm{
-			// 					wait_timer: Timer::from_seconds(4.0, TimerMode::Once),
-			// 					slam_timer: Timer::from_seconds(4.0, TimerMode::Once),
-			// 				}
-			// 			));
-			// 		}
-			// 	},
-			// 	_ => (),
-			// }
+// Don't carry panic over into the next level
+fn clear_spreading(
+	mut spreading: ResMut<SpreadingScares>,
+) {
+	spreading.0.clear();
+}
+
+// Goats that went over the edge carry on a little and drop below the land
+// while fading out, then count as lost
+fn goat_fall(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut goat_query: Query<(Entity, &mut Transform, &mut Sprite, &mut GoatMovement)>,
+	mut goat_numbers: ResMut<GoatNumbers>,
+) {
+	for (entity, mut transform, mut sprite, mut goat_movement) in goat_query.iter_mut() {
+		if goat_movement.state() != GoatState::Falling {
+			continue;
+		}
+		goat_movement.state_timer.tick(time.delta());
+		let fall_time = goat_movement.state_timer.elapsed_secs();
+		let drift = cart_to_iso(goat_movement.velocity) * goat_movement.speed * 0.5 * time.delta_seconds();
+		transform.translation.x += drift.x;
+		transform.translation.y += drift.y - FALL_GRAVITY * fall_time * time.delta_seconds();
+		sprite.color.set_a(1.0 - goat_movement.state_timer.fraction());
+		if goat_movement.state_timer.just_finished() {
+			commands.entity(entity).despawn_recursive();
+			goat_numbers.lose(LossCause::Fell);
 		}
-		goat_spawn_timer.goatbird_wave = (goat_spawn_timer.goatbird_wave + 1) % 32;
 	}
-}
\ No newline at end of file
+}
+
+// One bleat as a goat goes over
+fn goat_fall_bleat(
+	mut transitions: EventReader<GoatTransition>,
+	audio: Res<Audio>,
+	volume: Res<Volume>,
+	asset_server: Res<AssetServer>,
+) {
+	for _ in transitions.read().filter(|transition| transition.to == GoatState::Falling) {
+		audio.play(asset_server.load("sfx/goat_meh_far.ogg")).with_volume((volume.sfx*SFX_SCALING).powf(2.0));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, SeedableRng};
+
+	use super::*;
+
+	#[test]
+	fn loss_summary_in_cause_order() {
+		let mut goat_numbers = GoatNumbers{spawned: 0, killed: 0, total: 0, extra: 0, rescued: 0, losses: HashMap::new()};
+		assert_eq!(goat_numbers.loss_summary(), "");
+		goat_numbers.lose(LossCause::Fell);
+		goat_numbers.lose(LossCause::Carried);
+		goat_numbers.lose(LossCause::Fell);
+		assert_eq!(goat_numbers.loss_summary(), "1 carried, 2 fell");
+		assert_eq!(goat_numbers.killed, 3);
+	}
+
+	#[test]
+	fn empty_breed_table_gives_plain() {
+		let mut rng = StdRng::seed_from_u64(1);
+		assert_eq!(pick_breed(&[], &mut rng), Breed::Plain);
+		let zeroed = [BreedWeight{breed: Breed::Ram, weight: 0.0}];
+		assert_eq!(pick_breed(&zeroed, &mut rng), Breed::Plain);
+	}
+
+	#[test]
+	fn breeds_picked_by_weight() {
+		let mut rng = StdRng::seed_from_u64(1);
+		let table = [BreedWeight{breed: Breed::Kid, weight: 3.0}, BreedWeight{breed: Breed::Ram, weight: 1.0}, BreedWeight{breed: Breed::Billy, weight: 0.0}];
+		let picks: Vec<Breed> = (0..4000).map(|_| pick_breed(&table, &mut rng)).collect();
+		let kids = picks.iter().filter(|breed| **breed == Breed::Kid).count();
+		assert!(!picks.contains(&Breed::Billy));
+		assert!((2800..3200).contains(&kids), "{} kids", kids);
+	}
+}
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::menu::{GoatHead, ScreenFade};
+use crate::{menu::{GoatHead, ScreenFade}, setup::ORTHO};
 
 // STATES
 #[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
@@ -12,6 +12,8 @@ pub enum GameState {
 	Menu,
 	Cutscene,
 	Game,
+	#[cfg(debug_assertions)]
+	Editor,
 }
 
 // COMPONENTS
@@ -62,4 +64,11 @@ pub fn iso_to_cart(
 	let abcd = (1.0, 1.0, -0.5, 0.5);
 	let det = 1.0/(abcd.0 * abcd.3 - abcd.1 * abcd.2);
 	Vec2::new(coords.x * abcd.3 + coords.y * -abcd.1, coords.x * -abcd.2 + coords.y * abcd.0) * det
+}
+
+// Z layer for things sorted by how far down the screen they stand, from 600 to 601
+pub fn depth_z(
+	iso_y: f32,
+) -> f32 {
+	(1.0 - (iso_y + ORTHO.y/2.0) / ORTHO.y) + 600.0
 }
\ No newline at end of file
@@ -1,7 +1,8 @@
 // Land module, for handling land placement and collisions
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
 
-use crate::{cutscene::{CutsceneState, CutsceneTracker, SceneName}, enemy::Arm, goat::{GoatMovement, GoatNumbers}, helper::GameState, menu::ScreenFade, player::PlayerMovement, setup::{GameMode, ProgressTracker, TargetGameState, ORTHO, REVERT}};
+use crate::{cutscene::{CutsceneState, CutsceneTracker, SceneName}, elevation::ground_height, goat::{Breed, GoatMovement, GoatNumbers}, helper::GameState, level::{CurrentLevel, Level, LevelSelect, WinCondition}, menu::ScreenFade, player::PlayerMovement, setup::{GameMode, ProgressTracker, TargetGameState, ORTHO, REVERT}, zone::{MovementModifier, Zone}};
 
 pub struct LandPlugin;
 
@@ -9,11 +10,13 @@ impl Plugin for LandPlugin {
 	fn build(&self, app: &mut App) {
 		app
 			.insert_resource(GoatsHerded(0))
+			.init_resource::<Pens>()
 			.insert_resource(TitleTimer(Timer::from_seconds(1.2, TimerMode::Once)))
 			.add_systems(OnEnter(GameState::Game), (
-				land_setup,
+				land_setup.in_set(LandSetup).after(LevelSelect),
 			))
 			.add_systems(Update, (
+				(count_pens, rebuild_land_graph).chain(),
 				update_ui_text,
 				return_to_title,
 			).run_if(in_state(GameState::Game)))
@@ -21,18 +24,437 @@ impl Plugin for LandPlugin {
 	}
 }
 
+// Systems that need the Land entities on entering the game run after this set
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LandSetup;
+
+// Where a goat that strays off each land tile should head, indexed by tile
 #[derive(Resource)]
 pub struct Anchors(pub Vec<Vec2>);
 
+// Convex collision shape in cart space, polygons can wind either way
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub enum LandShape {
+	Rect{center: Vec2, size: Vec2},
+	Polygon(Vec<Vec2>),
+}
+
+impl LandShape {
+	// Corners in anticlockwise order
+	pub fn points(
+		&self,
+	) -> Vec<Vec2> {
+		match self {
+			LandShape::Rect{center, size} => {
+				let (min, max) = (*center - *size/2.0, *center + *size/2.0);
+				vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)]
+			},
+			LandShape::Polygon(points) => {
+				let mut area = 0.0;
+				for i in 0..points.len() {
+					area += points[i].perp_dot(points[(i+1)%points.len()]);
+				}
+				if area < 0.0 {points.iter().rev().copied().collect()} else {points.clone()}
+			},
+		}
+	}
+
+	pub fn contains(
+		&self,
+		point: Vec2,
+	) -> bool {
+		match self {
+			LandShape::Rect{center, size} => {
+				(point.x - center.x).abs() < size.x/2.0
+				&& (point.y - center.y).abs() < size.y/2.0
+			},
+			// Inside when on the same side of every edge, whichever way the points wind
+			LandShape::Polygon(points) => {
+				let mut sides = (0..points.len()).map(|i| edge_side(points[i], points[(i+1)%points.len()], point));
+				points.len() > 2 && (sides.clone().all(|side| side > 0.0) || sides.all(|side| side < 0.0))
+			},
+		}
+	}
+
+	pub fn center(
+		&self,
+	) -> Vec2 {
+		match self {
+			LandShape::Rect{center, ..} => *center,
+			LandShape::Polygon(points) => points.iter().sum::<Vec2>() / points.len().max(1) as f32,
+		}
+	}
+
+	// Size of the bounding box
+	pub fn size(
+		&self,
+	) -> Vec2 {
+		match self {
+			LandShape::Rect{size, ..} => *size,
+			LandShape::Polygon(points) => {
+				let min = points.iter().fold(Vec2::INFINITY, |min, point| min.min(*point));
+				let max = points.iter().fold(Vec2::NEG_INFINITY, |max, point| max.max(*point));
+				(max - min).max(Vec2::ZERO)
+			},
+		}
+	}
+
+	pub fn translated(
+		&self,
+		offset: Vec2,
+	) -> LandShape {
+		match self {
+			LandShape::Rect{center, size} => LandShape::Rect{center: *center + offset, size: *size},
+			LandShape::Polygon(points) => LandShape::Polygon(points.iter().map(|point| *point + offset).collect()),
+		}
+	}
+
+	pub fn scaled(
+		&self,
+		scale: f32,
+	) -> LandShape {
+		match self {
+			LandShape::Rect{center, size} => LandShape::Rect{center: *center * scale, size: *size * scale},
+			LandShape::Polygon(points) => LandShape::Polygon(points.iter().map(|point| *point * scale).collect()),
+		}
+	}
+
+	// Area shared with another shape, clipped edge by edge. Shapes that only
+	// touch give a degenerate polygon along the shared edge.
+	pub fn overlap(
+		&self,
+		other: &LandShape,
+	) -> Vec<Vec2> {
+		let mut output = self.points();
+		let clip = other.points();
+		for i in 0..clip.len() {
+			let (a, b) = (clip[i], clip[(i+1)%clip.len()]);
+			let input = output;
+			output = Vec::new();
+			for j in 0..input.len() {
+				let (p, q) = (input[j], input[(j+1)%input.len()]);
+				let (p_side, q_side) = (edge_side(a, b, p), edge_side(a, b, q));
+				if p_side >= 0.0 {
+					output.push(p);
+				}
+				if (p_side >= 0.0) != (q_side >= 0.0) {
+					output.push(p + (q - p) * p_side / (p_side - q_side));
+				}
+			}
+		}
+		output
+	}
+}
+
+// Level data for a goal pen. Pens with a capacity stop taking goats once full.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Pen{
+	pub shape: LandShape,
+	#[serde(default)]
+	pub name: String,
+	#[serde(default)]
+	pub capacity: Option<usize>,
+	#[serde(default)]
+	pub required: Option<usize>,
+	// Breeds the pen takes, any when empty
+	#[serde(default)]
+	pub accepts: Vec<Breed>,
+}
+
+impl Pen {
+	pub fn new(
+		shape: LandShape,
+	) -> Pen {
+		Pen{
+			shape,
+			name: String::new(),
+			capacity: None,
+			required: None,
+			accepts: Vec::new(),
+		}
+	}
+
+	pub fn accepts_breed(
+		&self,
+		breed: Breed,
+	) -> bool {
+		self.accepts.is_empty() || self.accepts.contains(&breed)
+	}
+
+	pub fn scaled(
+		&self,
+		scale: f32,
+	) -> Pen {
+		Pen{
+			shape: self.shape.scaled(scale),
+			..self.clone()
+		}
+	}
+}
+
+// Positive when the point is to the left of the edge from a to b
+fn edge_side(
+	a: Vec2,
+	b: Vec2,
+	point: Vec2,
+) -> f32 {
+	(b - a).perp_dot(point - a)
+}
+
+// Land tiles as nodes, joined wherever two of them overlap
+#[derive(Resource, Default)]
+pub struct LandGraph{
+	pub tiles: Vec<LandShape>,
+	// Neighbouring tile and the point where the two tiles meet
+	pub edges: Vec<Vec<(usize, Vec2)>>,
+	pub goal_tiles: Vec<usize>,
+}
+
+impl LandGraph {
+	pub fn new(
+		tiles: Vec<LandShape>,
+		goal_tiles: Vec<usize>,
+	) -> LandGraph {
+		let mut edges = vec![Vec::new(); tiles.len()];
+		for a in 0..tiles.len() {
+			for b in (a+1)..tiles.len() {
+				let overlap = tiles[a].overlap(&tiles[b]);
+				if !overlap.is_empty() {
+					let crossing = overlap.iter().sum::<Vec2>() / overlap.len() as f32;
+					edges[a].push((b, crossing));
+					edges[b].push((a, crossing));
+				}
+			}
+		}
+		LandGraph{
+			tiles,
+			edges,
+			goal_tiles,
+		}
+	}
+
+	// Goals, islands and dynamic land are also land, indexed after the land tiles
+	pub fn from_level(
+		level: &Level,
+	) -> LandGraph {
+		let tiles = level.land.iter()
+			.chain(level.goals.iter().map(|pen| &pen.shape))
+			.chain(level.islands.iter())
+			.chain(level.dynamic_land.iter().map(|dynamic| &dynamic.shape))
+			.cloned()
+			.collect();
+		let goal_tiles = (level.land.len()..level.land.len() + level.goals.len()).collect();
+		LandGraph::new(tiles, goal_tiles)
+	}
+
+	// Cuts a tile off from its neighbours, for land that has fallen away
+	pub fn isolate(
+		&mut self,
+		tile: usize,
+	) {
+		for (neighbour, _) in std::mem::take(&mut self.edges[tile]) {
+			self.edges[neighbour].retain(|(other, _)| *other != tile);
+		}
+	}
+
+	fn edge_cost(
+		&self,
+		from: usize,
+		crossing: Vec2,
+		to: usize,
+	) -> f32 {
+		self.tiles[from].center().distance(crossing) + crossing.distance(self.tiles[to].center())
+	}
+
+	// Dijkstra outwards from every goal at once, unreachable tiles stay infinite
+	pub fn goal_distances(
+		&self,
+	) -> Vec<f32> {
+		let mut distances = vec![f32::INFINITY; self.tiles.len()];
+		let mut visited = vec![false; self.tiles.len()];
+		for goal in self.goal_tiles.iter() {
+			distances[*goal] = 0.0;
+		}
+		while let Some(current) = (0..self.tiles.len())
+			.filter(|i| !visited[*i] && distances[*i].is_finite())
+			.min_by(|a, b| distances[*a].total_cmp(&distances[*b]))
+		{
+			visited[current] = true;
+			for (neighbour, crossing) in self.edges[current].iter() {
+				let distance = distances[current] + self.edge_cost(current, *crossing, *neighbour);
+				if distance < distances[*neighbour] {
+					distances[*neighbour] = distance;
+				}
+			}
+		}
+		distances
+	}
+
+	// Flow field over the tiles, each one points at the crossing into the
+	// neighbour on the shortest route to a goal. Goals and tiles with no
+	// route point at their own centre.
+	pub fn flow_field(
+		&self,
+	) -> Vec<Vec2> {
+		let distances = self.goal_distances();
+		let mut field = Vec::new();
+		for tile in 0..self.tiles.len() {
+			let mut waypoint = self.tiles[tile].center();
+			if !self.goal_tiles.contains(&tile) && distances[tile].is_finite() {
+				let mut best = f32::INFINITY;
+				for (neighbour, crossing) in self.edges[tile].iter() {
+					let distance = distances[*neighbour] + self.edge_cost(tile, *crossing, *neighbour);
+					if distance < best {
+						best = distance;
+						waypoint = *crossing;
+					}
+				}
+			}
+			field.push(waypoint);
+		}
+		field
+	}
+}
+
 #[derive(Component)]
 pub struct Land{
-	pub half_size: Vec2,
+	pub shape: LandShape,
 	pub tile_index: usize,
+	// Dynamic land can stop being walkable without being despawned
+	pub solid: bool,
 }
 
 #[derive(Component)]
 pub struct Goal{
-	pub half_size: Vec2,
+	pub shape: LandShape,
+	pub pen: usize,
+}
+
+pub struct PenState{
+	pub pen: Pen,
+	pub tile_index: usize,
+	pub herded: usize,
+}
+
+impl PenState {
+	pub fn is_full(
+		&self,
+	) -> bool {
+		!self.has_room(0)
+	}
+
+	// Room for one more goat on top of the herded count and those entering
+	pub fn has_room(
+		&self,
+		entering: usize,
+	) -> bool {
+		self.pen.capacity.is_none_or(|capacity| self.herded + entering < capacity)
+	}
+
+	// What the AllPens win condition asks of this pen
+	pub fn target(
+		&self,
+	) -> Option<usize> {
+		self.pen.required.or(self.pen.capacity)
+	}
+
+	// A pen with nothing asked of it is never done, rather than done from the start
+	pub fn is_done(
+		&self,
+	) -> bool {
+		self.target().is_some_and(|target| self.herded >= target)
+	}
+
+	fn summary(
+		&self,
+		index: usize,
+	) -> String {
+		let mut name = if self.pen.name.is_empty() {format!("Pen {}", index + 1)} else {self.pen.name.clone()};
+		if !self.pen.accepts.is_empty() {
+			let breeds: Vec<String> = self.pen.accepts.iter().map(|breed| format!("{:?}", breed)).collect();
+			name = format!("{} ({})", name, breeds.join(", "));
+		}
+		let target = self.target().map_or(String::new(), |target| format!("/{}", target));
+		let full = if self.is_full() {" (Full)"} else {""};
+		format!("{}: {}{}{}", name, self.herded, target, full)
+	}
+}
+
+// Goats herded into each pen, in level order
+#[derive(Resource, Default)]
+pub struct Pens(pub Vec<PenState>);
+
+// Shared point-in-land query for anything that walks on the map
+#[derive(SystemParam)]
+pub struct Ground<'w, 's> {
+	land_query: Query<'w, 's, &'static Land>,
+	goal_query: Query<'w, 's, &'static Goal>,
+	zone_query: Query<'w, 's, &'static Zone>,
+	level: Res<'w, CurrentLevel>,
+}
+
+impl<'w, 's> Ground<'w, 's> {
+	// Highest indexed solid land tile under the point
+	pub fn tile_at(
+		&self,
+		point: Vec2,
+	) -> Option<usize> {
+		self.land_query.iter()
+			.filter(|land| land.solid && land.shape.contains(point))
+			.map(|land| land.tile_index)
+			.max()
+	}
+
+	pub fn is_solid(
+		&self,
+		tile_index: usize,
+	) -> bool {
+		self.land_query.iter().any(|land| land.tile_index == tile_index && land.solid)
+	}
+
+	// Solid tile with its centre closest to the point
+	pub fn nearest_tile(
+		&self,
+		point: Vec2,
+	) -> Option<usize> {
+		self.land_query.iter()
+			.filter(|land| land.solid)
+			.min_by(|a, b| a.shape.center().distance(point).total_cmp(&b.shape.center().distance(point)))
+			.map(|land| land.tile_index)
+	}
+
+	pub fn on_land(
+		&self,
+		point: Vec2,
+	) -> bool {
+		self.tile_at(point).is_some()
+	}
+
+	// Every zone under the point, combined
+	pub fn modifier_at(
+		&self,
+		point: Vec2,
+	) -> MovementModifier {
+		self.zone_query.iter()
+			.filter(|zone| zone.shape.contains(point))
+			.fold(MovementModifier::NONE, |modifier, zone| modifier.combine(zone.kind.modifier()))
+	}
+
+	pub fn height_at(
+		&self,
+		point: Vec2,
+	) -> f32 {
+		ground_height(&self.level.0.elevations, point)
+	}
+
+	pub fn pen_at(
+		&self,
+		point: Vec2,
+	) -> Option<usize> {
+		self.goal_query.iter()
+			.find(|goal| goal.shape.contains(point))
+			.map(|goal| goal.pen)
+	}
 }
 
 fn land_setup(
@@ -40,77 +462,18 @@ fn land_setup(
 	asset_server: Res<AssetServer>,
 	mut progress_tracker: ResMut<ProgressTracker>,
 	mut goat_numbers: ResMut<GoatNumbers>,
-	mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+	level: Res<CurrentLevel>,
 ) {
 	progress_tracker.win_timer.reset();
 	progress_tracker.lose_timer.reset();
 
 	goat_numbers.spawned = 0;
-	goat_numbers.killed = 0;
-
-	match progress_tracker.mode {
-		GameMode::Tutorial => (),
-		GameMode::Campaign(i) | GameMode::Endless(i) => {
-			if i > 1 {
-				let texture_atlas_layout = texture_atlas_layouts.add(TextureAtlasLayout::from_grid(Vec2::new(1920.0, 1080.0), 2, 1, Some(Vec2::ZERO), Some(Vec2::ZERO)));
-				commands.spawn((SpriteSheetBundle {
-					transform: Transform::from_xyz(0.0, 0.0,  553.0),
-					texture: asset_server.load("arm.png"),
-					atlas: TextureAtlas{
-						layout: texture_atlas_layout,
-						index: 1,
-					},
-					sprite: Sprite {
-						custom_size: Some(ORTHO),
-						..default()
-					},
-					..default()
-					},
-					Arm{
-						wait_timer: Timer::from_seconds(6.1, TimerMode::Once),
-						slam_timer: Timer::from_seconds(1.3, TimerMode::Once),
-					}
-				));
-			}
-		},
-	}
-
-	commands.spawn((SpriteBundle {
-		transform: Transform::from_xyz(0.0, 0.0,  870.0),
-		texture: asset_server.load("fence.png"),
-		sprite: Sprite {
-			custom_size: Some(ORTHO),
-			..default()
-		},
-		..default()
-		},
-	));
-
-	commands.spawn((SpriteBundle {
-		transform: Transform::from_xyz(0.0, 0.0,  450.0),
-		texture: asset_server.load("iso_map.png"),
-		sprite: Sprite {
-			custom_size: Some(ORTHO),
-			..default()
-		},
-		..default()
-		},
-	));
-	commands.spawn((SpriteBundle {
-		transform: Transform::from_xyz(0.0, 0.0,  550.0),
-		texture: asset_server.load("iso_map_top.png"),
-		sprite: Sprite {
-			custom_size: Some(ORTHO),
-			..default()
-		},
-		..default()
-		},
-	));
+	goat_numbers.clear_losses();
 
-	if progress_tracker.mode == GameMode::Tutorial {
+	for background in level.0.backgrounds.iter() {
 		commands.spawn((SpriteBundle {
-			transform: Transform::from_xyz(0.0, 0.0,  551.0),
-			texture: asset_server.load("iso_map_tutorial.png"),
+			transform: Transform::from_xyz(0.0, 0.0,  background.z),
+			texture: asset_server.load(&background.image),
 			sprite: Sprite {
 				custom_size: Some(ORTHO),
 				..default()
@@ -153,60 +516,32 @@ fn land_setup(
 			));
 		});
 
-	commands.spawn((SpriteBundle {
-		transform: Transform::from_xyz(750.0*REVERT, -50.0*REVERT,  210.0),
-		sprite: Sprite {
-			color: Color::rgba(1.0, 0.1, 0.1, 0.0),
-			custom_size: Some(Vec2::new(200.0, 300.0)*REVERT),
+	for (i, pen) in level.0.goals.iter().enumerate() {
+		commands.spawn((SpriteBundle {
+			transform: Transform::from_translation(pen.shape.center().extend(210.0)),
+			sprite: Sprite {
+				color: Color::rgba(1.0, 0.1, 0.1, 0.0),
+				custom_size: Some(pen.shape.size()),
+				..default()
+			},
 			..default()
-		},
-		..default()
-		},
-		Goal{
-			half_size: Vec2::new(200.0, 300.0)*REVERT/2.0,
-		},
-	));
-
-	let locs = [
-		Vec2::new(-650.0, 0.0)*REVERT,
-		Vec2::new(-350.0, -150.0)*REVERT,
-		Vec2::new(-100.0, 50.0)*REVERT,
-		Vec2::new(150.0, 300.0)*REVERT,
-		Vec2::new(387.5, 100.0)*REVERT,
-		Vec2::new(650.0, -50.0)*REVERT,
-
-		Vec2::new(750.0, -50.0)*REVERT, // Goal
-		Vec2::new(-400.0, 225.0)*REVERT, // Island
-		Vec2::new(175.0, 50.0)*REVERT, // Island
-		Vec2::new(175.0, -175.0)*REVERT, // Island
-	];
-
-	let sizes = [
-		Vec2::new(400.0, 200.0)*REVERT,
-		Vec2::new(400.0, 200.0)*REVERT,
-		Vec2::new(300.0, 550.0)*REVERT,
-		Vec2::new(400.0, 150.0)*REVERT,
-		Vec2::new(175.0, 500.0)*REVERT,
-		Vec2::new(400.0, 300.0)*REVERT,
-
-		Vec2::new(200.0, 300.0)*REVERT, // Goal
-		Vec2::new(175.0, 150.0)*REVERT, // Island
-		Vec2::new(125.0, 125.0)*REVERT, // Island
-		Vec2::new(150.0, 150.0)*REVERT, // Island
-	];
-
-	let mut anchors = Vec::new();
-	for i in 0..6 {
-		let mut sign = (1.0, 1.0, 1.0, 1.0);
-		if locs[i].x < locs[i+1].x {sign.1 = -1.0} else {sign.0 = -1.0};
-		if locs[i].y < locs[i+1].y {sign.3 = -1.0} else {sign.2 = -1.0};
-		anchors.push(
-			Vec2::new(
-				((locs[i].x + sign.0*sizes[i].x / 2.0) + (locs[i+1].x + sign.1*sizes[i+1].x / 2.0)) / 2.0,
-				((locs[i].y + sign.2*sizes[i].y / 2.0) + (locs[i+1].y + sign.3*sizes[i+1].y / 2.0)) / 2.0,
-			),
-		);
+			},
+			Goal{
+				shape: pen.shape.clone(),
+				pen: i,
+			},
+		));
 	}
+	// Pens are indexed straight after the land tiles
+	commands.insert_resource(Pens(level.0.goals.iter().enumerate().map(|(i, pen)| PenState{
+		pen: pen.clone(),
+		tile_index: level.0.land.len() + i,
+		herded: 0,
+	}).collect()));
+
+	let land_graph = LandGraph::from_level(&level.0);
+	let tiles = land_graph.tiles.clone();
+	let anchors = land_graph.flow_field();
 
 	// for i in 0..anchors.len() {
 	// 	let anchor = cart_to_iso(anchors[i]);
@@ -222,6 +557,7 @@ fn land_setup(
 	// }
 
 	commands.insert_resource(Anchors(anchors));
+	commands.insert_resource(land_graph);
 
 	// let locs = [
 	// 	Vec2::new(0.0, 0.0)*REVERT,
@@ -237,19 +573,20 @@ fn land_setup(
 	// 	Vec2::new(100.0, 100.0)*REVERT,
 	// ];
 
-	for i in 0..locs.len() {
+	for (i, tile) in tiles.iter().enumerate() {
 		commands.spawn((SpriteBundle {
-			transform: Transform::from_xyz(locs[i].x, locs[i].y,  210.0),
+			transform: Transform::from_translation(tile.center().extend(210.0)),
 			sprite: Sprite {
 				color: Color::rgba(1.0, 0.1, 0.1, 0.0),
-				custom_size: Some(sizes[i]),
+				custom_size: Some(tile.size()),
 				..default()
 			},
 			..default()
 			},
 			Land{
-				half_size: sizes[i]/2.0,
+				shape: tile.clone(),
 				tile_index: i,
+				solid: true,
 			},
 		));
 	}
@@ -322,6 +659,48 @@ fn land_setup(
 	
 }
 
+fn count_pens(
+	goat_query: Query<&GoatMovement>,
+	mut pens: ResMut<Pens>,
+) {
+	let mut herded = vec![0; pens.0.len()];
+	for pen in goat_query.iter().filter_map(|goat| goat.pen()) {
+		if let Some(count) = herded.get_mut(pen) {
+			*count += 1;
+		}
+	}
+	// Only touch the resource when a count moves, so the graph isn't rebuilt every frame
+	if pens.0.iter().map(|pen| pen.herded).ne(herded.iter().copied()) {
+		for (pen, count) in pens.0.iter_mut().zip(herded) {
+			pen.herded = count;
+		}
+	}
+}
+
+// Keeps the tile graph and anchors in step with land that moved or fell
+// away, and stops routing goats to pens that are full
+fn rebuild_land_graph(
+	changed_query: Query<(), Changed<Land>>,
+	land_query: Query<&Land>,
+	pens: Res<Pens>,
+	mut land_graph: ResMut<LandGraph>,
+	mut anchors: ResMut<Anchors>,
+) {
+	if changed_query.is_empty() && !pens.is_changed() {
+		return;
+	}
+	let mut lands: Vec<&Land> = land_query.iter().collect();
+	lands.sort_by_key(|land| land.tile_index);
+	let tiles = lands.iter().map(|land| land.shape.clone()).collect();
+	let goal_tiles = pens.0.iter().filter(|pen| !pen.is_full()).map(|pen| pen.tile_index).collect();
+	let mut graph = LandGraph::new(tiles, goal_tiles);
+	for land in lands.iter().filter(|land| !land.solid) {
+		graph.isolate(land.tile_index);
+	}
+	anchors.0 = graph.flow_field();
+	*land_graph = graph;
+}
+
 #[derive(Resource)]
 pub struct GoatsHerded(pub usize);
 
@@ -339,25 +718,65 @@ pub fn get_ui_text_style(
 	}
 }
 
+// Goats, pens and level the HUD and the win check read
+#[derive(SystemParam)]
+struct HerdGoal<'w, 's> {
+	goat_query: Query<'w, 's, &'static GoatMovement>,
+	goats_herded: ResMut<'w, GoatsHerded>,
+	goat_numbers: ResMut<'w, GoatNumbers>,
+	pens: Res<'w, Pens>,
+	level: Res<'w, CurrentLevel>,
+}
+
+// Fades out of the level into a cutscene
+#[derive(SystemParam)]
+struct SceneChange<'w, 's> {
+	commands: Commands<'w, 's>,
+	cutscene_tracker: ResMut<'w, CutsceneTracker>,
+	target_state: ResMut<'w, TargetGameState>,
+	next_state: ResMut<'w, NextState<GameState>>,
+}
+
+impl<'w, 's> SceneChange<'w, 's> {
+	fn start(
+		&mut self,
+		scene: SceneName,
+	) {
+		self.target_state.state = GameState::Cutscene;
+		self.cutscene_tracker.current_scene = scene;
+		self.cutscene_tracker.cutscene_state = CutsceneState::Initialize;
+		self.next_state.set(GameState::Transition);
+		self.commands.spawn((SpriteBundle {
+			transform: Transform::from_xyz(0.0, 0.0, 950.0),
+			sprite: Sprite {
+				color: Color::rgba(0.0, 0.0, 0.0, 0.0),
+				custom_size: Some(ORTHO),
+				..default()
+			},
+			..default()
+			},
+			ScreenFade{
+				up: true,
+				timer: Timer::from_seconds(0.5, TimerMode::Once)
+			},
+		));
+	}
+}
+
 fn update_ui_text(
-	mut text_query: Query<(&mut Text, &UIText)>,
-	mut goats_herded: ResMut<GoatsHerded>,
+	mut text_query: Query<&mut Text, With<UIText>>,
 	time: Res<Time>,
 	player_query: Query<&PlayerMovement>,
-	goat_query: Query<&GoatMovement>,
 	asset_server: Res<AssetServer>,
 	mut progress_tracker: ResMut<ProgressTracker>,
-	mut cutscene_tracker: ResMut<CutsceneTracker>,
-	mut target_state: ResMut<TargetGameState>,
-	mut next_state: ResMut<NextState<GameState>>,
-	mut commands: Commands,
-	mut goat_numbers: ResMut<GoatNumbers>,
+	mut herd: HerdGoal,
+	mut scene_change: SceneChange,
 ) {
 	let mut safe_goats = 0;
-	for goat in goat_query.iter() {
-		if goat.safe {safe_goats += 1;};
+	for goat in herd.goat_query.iter() {
+		if goat.is_penned() {safe_goats += 1;};
 	}
-	goats_herded.0 = safe_goats;
+	herd.goats_herded.0 = safe_goats;
 	let mut selected_bell = "";
 	for player in player_query.iter() {
 		selected_bell = match player.selected_bell as usize {
@@ -378,6 +797,10 @@ fn update_ui_text(
 		},
 		GameMode::Endless(i) => 10 + i * 5,
 	};
+	let goats_required = match herd.level.0.win {
+		WinCondition::Total => goats_required,
+		WinCondition::AllPens => herd.pens.0.iter().map(|pen| pen.target().unwrap_or(0)).sum(),
+	};
 	let mut total_goats = match progress_tracker.mode {
 		GameMode::Tutorial => 20,
 		GameMode::Campaign(i) => match i {
@@ -386,88 +809,49 @@ fn update_ui_text(
 			2 => 50,
 			_ => 100,
 		},
-		GameMode::Endless(i) => goats_required + 20,
+		GameMode::Endless(_) => goats_required + 20,
 	};
-	goat_numbers.total = total_goats;
-	total_goats = (total_goats as isize - goat_numbers.killed as isize).clamp(0, 9999) as usize;
-	for (mut text, ui_text) in text_query.iter_mut() {
+	herd.goat_numbers.total = total_goats;
+	total_goats = ((total_goats + herd.goat_numbers.extra) as isize - herd.goat_numbers.killed as isize).clamp(0, 9999) as usize;
+	// Levels with more than one pen list each of them under the total
+	let losses = herd.goat_numbers.loss_summary();
+	let losses = if losses.is_empty() {losses} else {format!(" ({})", losses)};
+	let pen_lines: String = if herd.pens.0.len() > 1 {
+		herd.pens.0.iter().enumerate().map(|(i, pen)| format!("\n  {}", pen.summary(i))).collect()
+	} else {
+		String::new()
+	};
+	for mut text in text_query.iter_mut() {
 		text.sections = vec![
 			TextSection::new(
-				format!("Goats Herded: {}\nRequired Goats: {}\nGoats Left: {}\nHeld Bell: {}", safe_goats, goats_required, total_goats, selected_bell),
+				format!("Goats Herded: {}{}\nRequired Goats: {}\nGoats Left: {}{}\nHeld Bell: {}", safe_goats, pen_lines, goats_required, total_goats, losses, selected_bell),
 				get_ui_text_style(&asset_server), 
 			)
 		];
 	}
 
-	if safe_goats >= goats_required {
+	let won = match herd.level.0.win {
+		WinCondition::Total => safe_goats >= goats_required,
+		WinCondition::AllPens => herd.pens.0.iter().all(|pen| pen.is_done()),
+	};
+	if won {
 		progress_tracker.win_timer.tick(time.delta());
 		if progress_tracker.win_timer.just_finished() {
-			goat_numbers.spawned = 0;
-			goat_numbers.killed = 0;
+			herd.goat_numbers.spawned = 0;
+			herd.goat_numbers.clear_losses();
 			match progress_tracker.mode {
 				GameMode::Tutorial => {
 					progress_tracker.mode = GameMode::Tutorial;
-					target_state.state = GameState::Cutscene;
-					cutscene_tracker.current_scene = SceneName::TutorialOutro;
-					cutscene_tracker.cutscene_state = CutsceneState::Initialize;
-					next_state.set(GameState::Transition);
-					commands.spawn((SpriteBundle {
-						transform: Transform::from_xyz(0.0, 0.0, 950.0),
-						sprite: Sprite {
-							color: Color::rgba(0.0, 0.0, 0.0, 0.0),
-							custom_size: Some(ORTHO),
-							..default()
-						},
-						..default()
-						},
-						ScreenFade{
-							up: true,
-							timer: Timer::from_seconds(0.5, TimerMode::Once)
-						},
-					));
+					scene_change.start(SceneName::TutorialOutro);
 				},
 				GameMode::Campaign(i) => {
 					progress_tracker.max_campaign = i+1;
 					progress_tracker.mode = GameMode::Campaign(i+1);
-					target_state.state = GameState::Cutscene;
-					cutscene_tracker.current_scene = SceneName::CampaignDay(i);
-					cutscene_tracker.cutscene_state = CutsceneState::Initialize;
-					next_state.set(GameState::Transition);
-					commands.spawn((SpriteBundle {
-						transform: Transform::from_xyz(0.0, 0.0, 950.0),
-						sprite: Sprite {
-							color: Color::rgba(0.0, 0.0, 0.0, 0.0),
-							custom_size: Some(ORTHO),
-							..default()
-						},
-						..default()
-						},
-						ScreenFade{
-							up: true,
-							timer: Timer::from_seconds(0.5, TimerMode::Once)
-						},
-					));
+					scene_change.start(SceneName::CampaignDay(i));
 				},
 				GameMode::Endless(i) => {
 					progress_tracker.mode = GameMode::Endless(i+1);
-					target_state.state = GameState::Cutscene;
-					cutscene_tracker.current_scene = SceneName::EndlessOutro;
-					cutscene_tracker.cutscene_state = CutsceneState::Initialize;
-					next_state.set(GameState::Transition);
-					commands.spawn((SpriteBundle {
-						transform: Transform::from_xyz(0.0, 0.0, 950.0),
-						sprite: Sprite {
-							color: Color::rgba(0.0, 0.0, 0.0, 0.0),
-							custom_size: Some(ORTHO),
-							..default()
-						},
-						..default()
-						},
-						ScreenFade{
-							up: true,
-							timer: Timer::from_seconds(0.5, TimerMode::Once)
-						},
-					));
+					scene_change.start(SceneName::EndlessOutro);
 				},
 			}
 		}
@@ -476,26 +860,9 @@ fn update_ui_text(
 	if total_goats < goats_required {
 		progress_tracker.lose_timer.tick(time.delta());
 		if progress_tracker.lose_timer.just_finished() {
-			goat_numbers.spawned = 0;
-			goat_numbers.killed = 0;
-			target_state.state = GameState::Cutscene;
-			cutscene_tracker.current_scene = SceneName::Failure;
-			cutscene_tracker.cutscene_state = CutsceneState::Initialize;
-			next_state.set(GameState::Transition);
-			commands.spawn((SpriteBundle {
-				transform: Transform::from_xyz(0.0, 0.0, 950.0),
-				sprite: Sprite {
-					color: Color::rgba(0.0, 0.0, 0.0, 0.0),
-					custom_size: Some(ORTHO),
-					..default()
-				},
-				..default()
-				},
-				ScreenFade{
-					up: true,
-					timer: Timer::from_seconds(0.5, TimerMode::Once)
-				},
-			));
+			herd.goat_numbers.spawned = 0;
+			herd.goat_numbers.clear_losses();
+			scene_change.start(SceneName::Failure);
 		}
 	}
 }
@@ -534,4 +901,142 @@ fn return_to_title(
 	} else {
 		title_timer.0.reset();
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn diamond() -> Vec<Vec2> {
+		vec![Vec2::new(0.0, -100.0), Vec2::new(100.0, 0.0), Vec2::new(0.0, 100.0), Vec2::new(-100.0, 0.0)]
+	}
+
+	fn pen_state(
+		capacity: Option<usize>,
+		required: Option<usize>,
+		herded: usize,
+	) -> PenState {
+		let mut pen = Pen::new(LandShape::Rect{center: Vec2::ZERO, size: Vec2::new(100.0, 100.0)});
+		pen.capacity = capacity;
+		pen.required = required;
+		PenState{pen, tile_index: 0, herded}
+	}
+
+	#[test]
+	fn pen_without_target_is_never_done() {
+		assert!(!pen_state(None, None, 0).is_done());
+		assert!(!pen_state(None, None, 50).is_done());
+	}
+
+	#[test]
+	fn pen_done_at_required_or_capacity() {
+		assert!(!pen_state(None, Some(3), 2).is_done());
+		assert!(pen_state(None, Some(3), 3).is_done());
+		assert!(pen_state(Some(4), None, 4).is_done());
+		// Required wins over capacity
+		assert!(!pen_state(Some(4), Some(6), 4).is_done());
+	}
+
+	#[test]
+	fn pen_room_counts_goats_entering() {
+		let pen = pen_state(Some(3), None, 1);
+		assert!(pen.has_room(1));
+		assert!(!pen.has_room(2));
+		assert!(!pen_state(Some(3), None, 3).has_room(0));
+		assert!(pen_state(None, None, 100).has_room(100));
+	}
+
+	#[test]
+	fn rect_contains() {
+		let rect = LandShape::Rect{center: Vec2::new(50.0, 0.0), size: Vec2::new(100.0, 40.0)};
+		assert!(rect.contains(Vec2::new(50.0, 0.0)));
+		assert!(rect.contains(Vec2::new(99.0, 19.0)));
+		assert!(!rect.contains(Vec2::new(101.0, 0.0)));
+		assert!(!rect.contains(Vec2::new(50.0, -21.0)));
+	}
+
+	#[test]
+	fn polygon_contains_either_winding() {
+		let anticlockwise = LandShape::Polygon(diamond());
+		let clockwise = LandShape::Polygon(diamond().into_iter().rev().collect());
+		for shape in [anticlockwise, clockwise] {
+			assert!(shape.contains(Vec2::ZERO));
+			assert!(shape.contains(Vec2::new(40.0, 40.0)));
+			assert!(!shape.contains(Vec2::new(60.0, 60.0)));
+			assert!(!shape.contains(Vec2::new(-101.0, 0.0)));
+		}
+	}
+
+	#[test]
+	fn degenerate_polygon_contains_nothing() {
+		let line = LandShape::Polygon(vec![Vec2::ZERO, Vec2::new(100.0, 0.0)]);
+		assert!(!line.contains(Vec2::new(50.0, 0.0)));
+	}
+
+	#[test]
+	fn overlapping_rects() {
+		let a = LandShape::Rect{center: Vec2::ZERO, size: Vec2::new(100.0, 100.0)};
+		let b = LandShape::Rect{center: Vec2::new(75.0, 0.0), size: Vec2::new(100.0, 100.0)};
+		let overlap = a.overlap(&b);
+		assert!(!overlap.is_empty());
+		let center = overlap.iter().sum::<Vec2>() / overlap.len() as f32;
+		assert!(center.distance(Vec2::new(37.5, 0.0)) < 0.01);
+	}
+
+	#[test]
+	fn apart_shapes_dont_overlap() {
+		let rect = LandShape::Rect{center: Vec2::new(300.0, 0.0), size: Vec2::new(100.0, 100.0)};
+		let polygon = LandShape::Polygon(diamond());
+		assert!(rect.overlap(&polygon).is_empty());
+		assert!(polygon.overlap(&rect).is_empty());
+	}
+
+	#[test]
+	fn touching_rects_share_an_edge() {
+		let a = LandShape::Rect{center: Vec2::ZERO, size: Vec2::new(100.0, 100.0)};
+		let b = LandShape::Rect{center: Vec2::new(100.0, 0.0), size: Vec2::new(100.0, 100.0)};
+		let overlap = a.overlap(&b);
+		assert!(!overlap.is_empty());
+		assert!(overlap.iter().all(|point| (point.x - 50.0).abs() < 0.01));
+	}
+
+	fn rect(
+		x: f32,
+		y: f32,
+	) -> LandShape {
+		LandShape::Rect{center: Vec2::new(x, y), size: Vec2::new(120.0, 120.0)}
+	}
+
+	#[test]
+	fn graph_joins_overlapping_tiles() {
+		let graph = LandGraph::new(vec![rect(0.0, 0.0), rect(100.0, 0.0), rect(500.0, 0.0)], vec![1]);
+		assert_eq!(graph.edges[0].len(), 1);
+		assert_eq!(graph.edges[0][0].0, 1);
+		assert!(graph.edges[2].is_empty());
+	}
+
+	#[test]
+	fn goal_distances_follow_the_chain() {
+		// 0 - 1 - 2 (goal), with 3 off on its own
+		let graph = LandGraph::new(vec![rect(0.0, 0.0), rect(100.0, 0.0), rect(200.0, 0.0), rect(600.0, 0.0)], vec![2]);
+		let distances = graph.goal_distances();
+		assert_eq!(distances[2], 0.0);
+		assert!(distances[1] > 0.0 && distances[0] > distances[1]);
+		assert!(distances[3].is_infinite());
+	}
+
+	#[test]
+	fn flow_field_points_along_the_route() {
+		// From 0 the goal at 3 is reached through 1, while 2 and 4 lead nowhere
+		let tiles = vec![rect(0.0, 0.0), rect(100.0, 0.0), rect(0.0, 100.0), rect(200.0, 0.0), rect(100.0, 200.0)];
+		let mut graph = LandGraph::new(tiles, vec![3]);
+		let field = graph.flow_field();
+		let crossing = graph.edges[0].iter().find(|(neighbour, _)| *neighbour == 1).unwrap().1;
+		assert_eq!(field[0], crossing);
+		// Goals and cut off tiles point at their own centre
+		assert_eq!(field[3], Vec2::new(200.0, 0.0));
+		graph.isolate(1);
+		let field = graph.flow_field();
+		assert_eq!(field[0], Vec2::ZERO);
+	}
+}
@@ -0,0 +1,194 @@
+// Level module, for loading level layouts from data files
+use bevy::{asset::{io::Reader, ron, AssetLoader, AsyncReadExt, LoadContext}, prelude::*, utils::BoxedFuture};
+use serde::{Deserialize, Serialize};
+
+use crate::{dynamic_land::DynamicLand, elevation::Elevation, enemy::ArmAttack, goat::BreedWeight, helper::GameState, land::{LandShape, Pen}, mapgen::generate_level, setup::{GameMode, ProgressTracker, REVERT}, wave::GoatSpawn, zone::Zone};
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_asset::<Level>()
+			.init_asset_loader::<LevelLoader>()
+			.init_resource::<CurrentLevel>()
+			.add_systems(OnEnter(GameState::Game), (
+				select_level.in_set(LevelSelect),
+			))
+		;
+	}
+}
+
+// Systems that read CurrentLevel on entering the game run after this set
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LevelSelect;
+
+// Layout of one map, loaded from assets/levels/*.level.ron.
+// Positions and sizes are in unscaled cart space, REVERT is applied on selection.
+// Shapes are a Rect(center, size) or a convex Polygon([points]).
+#[derive(Asset, TypePath, Deserialize, Serialize, Clone, Default)]
+pub struct Level {
+	// Art drawn under and over the goats, by z
+	pub backgrounds: Vec<LevelImage>,
+	// Walkable tiles, joined wherever two of them overlap
+	pub land: Vec<LandShape>,
+	// Pens, each with an optional name, capacity, required count and accepted breeds
+	pub goals: Vec<Pen>,
+	// Land off to the side of the main path, usually cut off from it
+	pub islands: Vec<LandShape>,
+	pub player_spawn: Vec2,
+	// Weighted goat entry points that can open, close and drift during the level
+	pub goat_spawns: Vec<GoatSpawn>,
+	pub spitter_spawns: Vec<Vec2>,
+	// Iso screen space, goatbirds appear somewhere along this line
+	pub goatbird_spawn_line: [Vec2; 2],
+	// Land that crumbles, comes and goes or drifts, indexed after the islands
+	#[serde(default)]
+	pub dynamic_land: Vec<DynamicLand>,
+	// Total (the mode's goat count) by default, or AllPens
+	#[serde(default)]
+	pub win: WinCondition,
+	// Patches of mud, ice, tall grass or shallow water over the land
+	#[serde(default)]
+	pub zones: Vec<Zone>,
+	// Chance a goat walking into the edge of the land slips over instead of turning back
+	#[serde(default = "default_edge_slip")]
+	pub edge_slip: f32,
+	// Spawn weights for each breed, plain goats only when empty
+	#[serde(default)]
+	pub breeds: Vec<BreedWeight>,
+	// Raised ground and ramps over the land, heights in unscaled iso pixels
+	#[serde(default)]
+	pub elevations: Vec<Elevation>,
+	// Arm attacks, brought in by an Arm entry in the wave script
+	#[serde(default)]
+	pub arms: Vec<ArmAttack>,
+	// Levels without background art have their land built from terrain tiles
+	#[serde(default)]
+	pub draw_land: bool,
+}
+
+pub const DEFAULT_EDGE_SLIP: f32 = 0.1;
+
+fn default_edge_slip() -> f32 {
+	DEFAULT_EDGE_SLIP
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum WinCondition {
+	// Enough goats herded across all pens for the game mode
+	#[default]
+	Total,
+	// Every pen reaches its required count, or fills up if it only has a capacity
+	AllPens,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct LevelImage {
+	pub image: String,
+	pub z: f32,
+}
+
+impl Level {
+	// Why the loader would turn this level down, if it would
+	pub fn problem(
+		&self,
+	) -> Option<&'static str> {
+		// Such a pen could never be done, so the level could never be won
+		if self.win == WinCondition::AllPens && self.goals.iter().any(|pen| pen.required.is_none() && pen.capacity.is_none()) {
+			return Some("AllPens levels need a required count or capacity on every pen");
+		}
+		None
+	}
+
+	pub fn scaled(
+		&self,
+		scale: f32,
+	) -> Level {
+		Level {
+			backgrounds: self.backgrounds.clone(),
+			land: self.land.iter().map(|shape| shape.scaled(scale)).collect(),
+			goals: self.goals.iter().map(|pen| pen.scaled(scale)).collect(),
+			islands: self.islands.iter().map(|shape| shape.scaled(scale)).collect(),
+			player_spawn: self.player_spawn * scale,
+			goat_spawns: self.goat_spawns.iter().map(|spawn| spawn.scaled(scale)).collect(),
+			spitter_spawns: self.spitter_spawns.iter().map(|spawn| *spawn * scale).collect(),
+			goatbird_spawn_line: self.goatbird_spawn_line,
+			dynamic_land: self.dynamic_land.iter().map(|dynamic| dynamic.scaled(scale)).collect(),
+			win: self.win,
+			zones: self.zones.iter().map(|zone| Zone{shape: zone.shape.scaled(scale), kind: zone.kind}).collect(),
+			edge_slip: self.edge_slip,
+			breeds: self.breeds.clone(),
+			elevations: self.elevations.iter().map(|elevation| elevation.scaled(scale)).collect(),
+			arms: self.arms.iter().map(|arm| arm.scaled(scale)).collect(),
+			draw_land: self.draw_land,
+		}
+	}
+}
+
+#[derive(Resource)]
+pub struct LevelHandles{
+	pub tutorial: Handle<Level>,
+	pub hillside: Handle<Level>,
+}
+
+impl LevelHandles {
+	pub fn for_mode(
+		&self,
+		mode: &GameMode,
+	) -> &Handle<Level> {
+		match mode {
+			GameMode::Tutorial => &self.tutorial,
+			// Endless maps come from generate_level, this is only so the
+			// editor has something to open from endless mode
+			GameMode::Campaign(_) | GameMode::Endless(_) => &self.hillside,
+		}
+	}
+}
+
+// Scaled copy of the level being played
+#[derive(Resource, Default)]
+pub struct CurrentLevel(pub Level);
+
+#[derive(Default)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+	type Asset = Level;
+	type Settings = ();
+	type Error = Box<dyn std::error::Error + Send + Sync>;
+
+	fn load<'a>(
+		&'a self,
+		reader: &'a mut Reader,
+		_settings: &'a (),
+		_load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<Level, Self::Error>> {
+		Box::pin(async move {
+			let mut bytes = Vec::new();
+			reader.read_to_end(&mut bytes).await?;
+			let level = ron::de::from_bytes::<Level>(&bytes)?;
+			if let Some(problem) = level.problem() {
+				return Err(problem.into());
+			}
+			Ok(level)
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["level.ron"]
+	}
+}
+
+fn select_level(
+	progress_tracker: Res<ProgressTracker>,
+	level_handles: Res<LevelHandles>,
+	levels: Res<Assets<Level>>,
+	mut current_level: ResMut<CurrentLevel>,
+) {
+	if let GameMode::Endless(i) = progress_tracker.mode {
+		current_level.0 = generate_level(progress_tracker.endless_seed.wrapping_add(i as u64)).scaled(REVERT);
+	} else if let Some(level) = levels.get(level_handles.for_mode(&progress_tracker.mode)) {
+		current_level.0 = level.scaled(REVERT);
+	}
+}
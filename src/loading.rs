@@ -2,7 +2,7 @@
 use bevy::{asset::LoadState, prelude::*};
 use bevy_kira_audio::AudioSource;
 
-use crate::helper::GameState;
+use crate::{helper::GameState, level::{Level, LevelHandles}, wave::{WaveHandles, WaveScript}};
 
 pub struct LoadingPlugin;
 
@@ -63,6 +63,7 @@ fn add_assets_to_load(
 	assets_to_load.push(asset_server.load::<Image>("spitter.png").untyped());
 	assets_to_load.push(asset_server.load::<Image>("spit.png").untyped());
 	assets_to_load.push(asset_server.load::<Image>("arm.png").untyped());
+	assets_to_load.push(asset_server.load::<Image>("burrower.png").untyped());
 
 	assets_to_load.push(asset_server.load::<Image>("bell_sel.png").untyped());
 
@@ -71,6 +72,23 @@ fn add_assets_to_load(
 	assets_to_load.push(asset_server.load::<Image>("iso_map_top.png").untyped());
 	assets_to_load.push(asset_server.load::<Image>("iso_map_tutorial.png").untyped());
 
+	let level_handles = LevelHandles{
+		tutorial: asset_server.load::<Level>("levels/tutorial.level.ron"),
+		hillside: asset_server.load::<Level>("levels/hillside.level.ron"),
+	};
+	assets_to_load.push(level_handles.tutorial.clone().untyped());
+	assets_to_load.push(level_handles.hillside.clone().untyped());
+	commands.insert_resource(level_handles);
+
+	let wave_handles = WaveHandles{
+		tutorial: asset_server.load::<WaveScript>("waves/tutorial.wave.ron"),
+		days: (1..=4).map(|day| asset_server.load::<WaveScript>(format!("waves/day_{}.wave.ron", day))).collect(),
+		endless: (1..=4).map(|tier| asset_server.load::<WaveScript>(format!("waves/endless_{}.wave.ron", tier))).collect(),
+	};
+	assets_to_load.push(wave_handles.tutorial.clone().untyped());
+	assets_to_load.extend(wave_handles.days.iter().chain(wave_handles.endless.iter()).map(|handle| handle.clone().untyped()));
+	commands.insert_resource(wave_handles);
+
 	commands.insert_resource(AssetsLoading(assets_to_load));
 }
 
@@ -9,18 +9,29 @@ use bevy_kira_audio::AudioPlugin;
 mod helper;
 
 mod cutscene;
+mod dynamic_land;
+mod elevation;
 mod enemy;
 mod goat;
 mod land;
+mod level;
 mod loading;
+mod mapgen;
 mod menu;
 mod player;
 mod post_proc;
+mod rng;
 mod setup;
+mod spatial;
+mod terrain;
+mod wave;
+mod zone;
 
 // Only include in debug builds
 #[cfg(debug_assertions)]
 mod debug;
+#[cfg(debug_assertions)]
+mod editor;
 
 fn main() {
 	let default_plugins = DefaultPlugins
@@ -48,28 +59,50 @@ fn main() {
 		.add_plugins((
 			// Intro animatic and dialogue
 			cutscene::CutscenePlugin,
+			// Crumbling, scheduled and drifting land
+			dynamic_land::DynamicLandPlugin,
+			// Raised ground, ramps and cliffs
+			elevation::ElevationPlugin,
 			// Enemy movement and interactions
 			enemy::EnemyPlugin,
 			// Goat movement and interations
 			goat::GoatPlugin,
 			// Land placement and interations
 			land::LandPlugin,
+			// Level data files, loaded as assets
+			level::LevelPlugin,
 			// State transitions, loads assets
 			loading::LoadingPlugin,
+		))
+		.add_plugins((
 			// Title screen
 			menu::MenuPlugin,
 			// Player movement and interactions
 			player::PlayerPlugin,
 			// Post processing effects such as chromatic aberration
 			post_proc::PostProcPlugin,
+			// Seeded randomness, the same seed gives the same levels
+			rng::RngPlugin,
 			// Spawns camera, splash screen, title, level
 			setup::SetupPlugin,
+			// Proximity lookups for goats and enemies
+			spatial::SpatialPlugin,
+			// Iso tile terrain for levels without background art
+			terrain::TerrainPlugin,
+			// Scripted goat and enemy spawns
+			wave::WavePlugin,
+			// Mud, ice and other ground that changes movement
+			zone::ZonePlugin,
 		))
 	;
 
 	{
 		#[cfg(debug_assertions)]
-		app.add_plugins(debug::DebugPlugin);
+		app.add_plugins((
+			debug::DebugPlugin,
+			// Level layout editor
+			editor::EditorPlugin,
+		));
 	}
 
 	{
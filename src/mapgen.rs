@@ -0,0 +1,182 @@
+// Map generation module, for building endless mode levels from a seed
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{enemy::{default_arm_down, ArmAttack}, goat::{Breed, BreedWeight}, helper::{cart_to_iso, iso_to_cart}, land::{LandShape, Pen}, level::{Level, WinCondition, DEFAULT_EDGE_SLIP}, setup::REVERT, wave::GoatSpawn, zone::{Zone, ZoneKind}};
+
+// Same entry and pen as the hillside map, so goats arrive and get herded where players expect
+const GOAT_SPAWN: Vec2 = Vec2::new(-800.0, 50.0);
+const GOAL_CENTER: Vec2 = Vec2::new(750.0, -50.0);
+const GOAL_SIZE: Vec2 = Vec2::new(200.0, 300.0);
+const GOATBIRD_SPAWN_LINE: [Vec2; 2] = [Vec2::new(640.0, 600.0), Vec2::new(956.8, 600.0)];
+
+// Iso screen space that generated waypoints and islands have to stay inside
+const VISIBLE_HALF_SIZE: Vec2 = Vec2::new(880.0, 470.0);
+// Bottom left corner covered by the goat count text
+const UI_MIN: Vec2 = Vec2::new(-960.0, -540.0);
+const UI_MAX: Vec2 = Vec2::new(-340.0, -160.0);
+
+const ISLANDS: usize = 3;
+// Gap kept between an island and any other tile, so goats can't walk onto it
+const ISLAND_CLEARANCE: f32 = 40.0;
+// Same slam size as the hillside arm, each one slamming every 14.8s
+const ARM_ZONE_SIZE: Vec2 = Vec2::new(200.0, 300.0);
+const ARM_WAIT: f32 = 13.5;
+
+// A chain of rects from the goat spawn to the goal, with islands off to the
+// side for spitters. Consecutive rects always overlap, so the goal is
+// reachable from every land tile.
+pub fn generate_level(
+	seed: u64,
+) -> Level {
+	let mut rng = StdRng::seed_from_u64(seed);
+
+	let bends = rng.gen_range(3..=5);
+	let mut waypoints = vec![GOAT_SPAWN];
+	for j in 1..=bends {
+		let frac = j as f32 / (bends + 1) as f32;
+		let straight = GOAT_SPAWN.lerp(GOAL_CENTER, frac);
+		let x = straight.x + rng.gen_range(-60.0..60.0);
+		let fallback = Vec2::new(x, straight.y);
+		let waypoint = (0..20)
+			.map(|_| Vec2::new(x, rng.gen_range(-450.0..450.0)))
+			.find(|point| visible(*point))
+			.unwrap_or(fallback);
+		waypoints.push(waypoint);
+	}
+	waypoints.push(GOAL_CENTER);
+
+	// Each leg between waypoints is an L of two rects meeting at a corner
+	let mut land = Vec::new();
+	for pair in waypoints.windows(2) {
+		let (start, end) = (pair[0], pair[1]);
+		let corner = if rng.gen_bool(0.5) {Vec2::new(end.x, start.y)} else {Vec2::new(start.x, end.y)};
+		for (a, b) in [(start, corner), (corner, end)] {
+			let width = rng.gen_range(120.0..200.0);
+			land.push(LandShape::Rect{
+				center: (a + b)/2.0,
+				size: (b - a).abs() + Vec2::splat(width),
+			});
+		}
+	}
+
+	// A few patches of difficult ground along the route
+	let kinds = [ZoneKind::Mud, ZoneKind::Ice, ZoneKind::TallGrass, ZoneKind::ShallowWater];
+	let zones = (0..rng.gen_range(0..=2))
+		.map(|_| {
+			let tile = &land[rng.gen_range(0..land.len())];
+			Zone{
+				shape: LandShape::Rect{center: tile.center(), size: tile.size() * 0.6},
+				kind: kinds[rng.gen_range(0..kinds.len())],
+			}
+		})
+		.collect();
+
+	let goals = vec![Pen::new(LandShape::Rect{center: GOAL_CENTER, size: GOAL_SIZE})];
+
+	let mut islands: Vec<LandShape> = Vec::new();
+	for _ in 0..ISLANDS {
+		for _ in 0..200 {
+			let iso_loc = Vec2::new(
+				rng.gen_range(-VISIBLE_HALF_SIZE.x..VISIBLE_HALF_SIZE.x),
+				rng.gen_range(-VISIBLE_HALF_SIZE.y..VISIBLE_HALF_SIZE.y),
+			);
+			let island = LandShape::Rect{
+				center: iso_to_cart(iso_loc) / REVERT,
+				size: Vec2::new(rng.gen_range(125.0..175.0), rng.gen_range(125.0..175.0)),
+			};
+			let clearance = LandShape::Rect{
+				center: island.center(),
+				size: island.size() + Vec2::splat(ISLAND_CLEARANCE * 2.0),
+			};
+			let clear = land.iter().chain(goals.iter().map(|pen| &pen.shape)).chain(islands.iter())
+				.all(|tile| clearance.overlap(tile).is_empty());
+			if clear && island.points().iter().all(|point| visible(*point)) {
+				islands.push(island);
+				break;
+			}
+		}
+	}
+
+	// Two arms taking turns over tiles along the route, each slamming half as often as a lone arm
+	let arms = (0..2)
+		.map(|i| {
+			let tile = &land[rng.gen_range(0..land.len())];
+			ArmAttack{
+				zones: vec![LandShape::Rect{center: tile.center(), size: tile.size().min(ARM_ZONE_SIZE)}],
+				wait: ARM_WAIT,
+				offset: i as f32 * (ARM_WAIT + default_arm_down()) / 2.0,
+				..default()
+			}
+		})
+		.collect();
+
+	Level {
+		backgrounds: Vec::new(),
+		land,
+		goals,
+		spitter_spawns: islands.iter().map(|island| island.center()).collect(),
+		islands,
+		player_spawn: GOAL_CENTER,
+		goat_spawns: vec![GoatSpawn::new(GOAT_SPAWN)],
+		goatbird_spawn_line: GOATBIRD_SPAWN_LINE,
+		win: WinCondition::Total,
+		dynamic_land: Vec::new(),
+		zones,
+		edge_slip: DEFAULT_EDGE_SLIP,
+		breeds: Breed::ALL.iter().map(|&breed| BreedWeight{breed, weight: breed.profile().weight}).collect(),
+		elevations: Vec::new(),
+		arms,
+		draw_land: true,
+	}
+}
+
+// Whether an unscaled cart point ends up on screen and clear of the UI
+fn visible(
+	point: Vec2,
+) -> bool {
+	let iso_loc = cart_to_iso(point * REVERT);
+	let on_screen = iso_loc.abs().cmplt(VISIBLE_HALF_SIZE).all();
+	let under_ui = iso_loc.cmpgt(UI_MIN).all() && iso_loc.cmplt(UI_MAX).all();
+	on_screen && !under_ui
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::land::LandGraph;
+
+	#[test]
+	fn every_tile_reaches_the_goal() {
+		for seed in 0..64 {
+			let level = generate_level(seed);
+			let distances = LandGraph::from_level(&level).goal_distances();
+			assert!(distances[..level.land.len()].iter().all(|distance| distance.is_finite()), "seed {}", seed);
+		}
+	}
+
+	#[test]
+	fn islands_stay_cut_off() {
+		for seed in 0..64 {
+			let level = generate_level(seed);
+			let distances = LandGraph::from_level(&level).goal_distances();
+			let first_island = level.land.len() + level.goals.len();
+			assert!(distances[first_island..first_island + level.islands.len()].iter().all(|distance| distance.is_infinite()), "seed {}", seed);
+		}
+	}
+
+	#[test]
+	fn goats_spawn_on_land() {
+		for seed in 0..64 {
+			let level = generate_level(seed);
+			assert!(level.land.iter().any(|tile| tile.contains(GOAT_SPAWN)), "seed {}", seed);
+		}
+	}
+
+	#[test]
+	fn same_seed_same_level() {
+		let (a, b) = (generate_level(7), generate_level(7));
+		assert_eq!(a.land.len(), b.land.len());
+		assert!(a.land.iter().zip(b.land.iter()).all(|(a, b)| a.center() == b.center() && a.size() == b.size()));
+	}
+}
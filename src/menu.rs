@@ -1,8 +1,9 @@
 // Menu module, for title screen
 use bevy::{app::AppExit, prelude::*, window::PrimaryWindow};
 use bevy_kira_audio::{Audio, AudioControl};
+use rand::Rng;
 
-use crate::{cutscene::{CutsceneState, CutsceneTracker, SceneName}, helper::GameState, setup::{GameMode, ProgressTracker, SelectedButton, TargetGameState, Volume, ORTHO, REVERT}};
+use crate::{cutscene::{CutsceneState, CutsceneTracker, SceneName}, helper::GameState, rng::{GameRng, RngStream}, setup::{GameMode, ProgressTracker, SelectedButton, TargetGameState, Volume, ORTHO, REVERT}};
 
 pub struct MenuPlugin;
 
@@ -149,6 +150,7 @@ fn highlight_selected_button(
 	mut progress_tracker: ResMut<ProgressTracker>,
 	mut cutscene_tracker: ResMut<CutsceneTracker>,
 	audio: Res<Audio>,
+	mut game_rng: ResMut<GameRng>,
 ) {
 	let screen = match *current_state.get() {
 		GameState::Config => 0.0,
@@ -308,6 +310,7 @@ fn highlight_selected_button(
 				},
 				2 => {
 					progress_tracker.mode = GameMode::Endless(0);
+					progress_tracker.endless_seed = game_rng.stream(RngStream::Endless).gen();
 					target_state.state = GameState::Game;
 					next_state.set(GameState::Transition);
 					commands.spawn((SpriteBundle {
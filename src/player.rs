@@ -4,7 +4,7 @@ use bevy_kira_audio::{Audio, AudioControl};
 
 use std::{f32::consts::PI, time::Duration};
 
-use crate::{enemy::{GoatbirdMovement, Spit, Spitter}, goat::GoatMovement, helper::{cart_to_iso, iso_to_cart, GameState}, land::Land, menu::SFX_SCALING, setup::{BellEvent, Volume, ORTHO, REVERT}};
+use crate::{elevation::{JUMP_CLIMB, MAX_STEP}, enemy::{Enemy, GoatbirdMovement}, goat::{Breed, GoatMovement, GoatState, GoatTransition}, helper::{cart_to_iso, depth_z, iso_to_cart, GameState}, land::Ground, level::{CurrentLevel, LevelSelect}, menu::SFX_SCALING, rng::{GameRng, RngStream}, setup::{BellEvent, Volume}, spatial::SpatialIndex, zone::MovementModifier};
 
 pub struct PlayerPlugin;
 
@@ -12,7 +12,7 @@ impl Plugin for PlayerPlugin {
 	fn build(&self, app: &mut App) {
 		app
 			.add_systems(OnEnter(GameState::Game), (
-				player_setup,
+				player_setup.after(LevelSelect),
 			))
 			.add_systems(Update, (
 				player_control,
@@ -38,7 +38,7 @@ struct BellCooldown(usize);
 
 #[derive(Component)]
 pub struct PlayerMovement{
-	cart_transform: Vec2,
+	pub cart_transform: Vec2,
 	velocity: Vec2,
 	airtime: Timer,
 	max_vel: f32,
@@ -49,13 +49,31 @@ pub struct PlayerMovement{
 	pub selected_bell: f32,
 	total_bells: f32,
 	bell_cooldown: [Timer; 5],
+	// Controls don't respond until this runs out
+	stun: Timer,
+}
+
+impl PlayerMovement {
+	pub fn airborne(
+		&self,
+	) -> bool {
+		!self.airtime.finished()
+	}
+
+	pub fn stun(
+		&mut self,
+		seconds: f32,
+	) {
+		self.stun = Timer::from_seconds(seconds, TimerMode::Once);
+	}
 }
 
 fn player_setup(
 	mut commands: Commands,
 	asset_server: Res<AssetServer>,
+	level: Res<CurrentLevel>,
 ) {
-	let cart_loc = Vec2::new(750.0, -50.0)*REVERT;
+	let cart_loc = level.0.player_spawn;
 	let iso_loc = cart_to_iso(cart_loc);
 	commands.spawn((SpriteBundle {
 		transform: Transform::from_xyz(iso_loc.x, iso_loc.y, 600.0),
@@ -84,6 +102,7 @@ fn player_setup(
 				Timer::from_seconds(3.0, TimerMode::Once).tick(Duration::from_secs(16)).clone(),
 				Timer::from_seconds(1.0, TimerMode::Once).tick(Duration::from_secs(16)).clone(),
 			],
+			stun: Timer::from_seconds(0.0, TimerMode::Once),
 		},
 	)).with_children(|parent| {
 		let shadow_iso_loc = Vec2::new(0.0, -32.0);
@@ -173,6 +192,7 @@ fn player_control(
 	time: Res<Time>,
 	keyboard: Res<ButtonInput<KeyCode>>,
 	mut player_query: Query<(&mut PlayerMovement)>,
+	ground: Ground,
 ) {
 	let mut mov_dir = Vec2::splat(0.0);
 	if keyboard.pressed(KeyCode::ArrowLeft) || keyboard.pressed(KeyCode::KeyA) {
@@ -188,13 +208,23 @@ fn player_control(
 		mov_dir.y -= 1.0;
 	}
 	for (mut player_movement) in player_query.iter_mut() {
+		player_movement.stun.tick(time.delta());
+		let mov_dir = if player_movement.stun.finished() {mov_dir} else {Vec2::ZERO};
+		// Zones only grip the player while on the ground
+		let modifier = if player_movement.airtime.finished() {
+			ground.modifier_at(player_movement.cart_transform - iso_to_cart(Vec2::new(0.0, 32.0)))
+		} else {
+			MovementModifier::NONE
+		};
+		let acceleration = player_movement.acceleration * modifier.turning;
+		let max_vel = player_movement.max_vel * modifier.speed;
 		if player_movement.iso_move {
-			player_movement.velocity = (player_movement.velocity + cart_to_iso(mov_dir) * player_movement.acceleration * time.delta_seconds()).clamp_length_max(player_movement.max_vel);
+			player_movement.velocity = (player_movement.velocity + cart_to_iso(mov_dir) * acceleration * time.delta_seconds()).clamp_length_max(max_vel);
 			//player_movement.velocity = (player_movement.velocity + mov_dir * player_movement.acceleration * time.delta_seconds()).clamp_length_max(player_movement.max_vel);
 		} else {
-			player_movement.velocity = (player_movement.velocity + mov_dir * player_movement.acceleration * time.delta_seconds()).clamp_length_max(player_movement.max_vel);
+			player_movement.velocity = (player_movement.velocity + mov_dir * acceleration * time.delta_seconds()).clamp_length_max(max_vel);
 		}
-		player_movement.velocity = player_movement.velocity / (1.0 + player_movement.friction * time.delta_seconds());
+		player_movement.velocity = player_movement.velocity / (1.0 + player_movement.friction * modifier.friction * time.delta_seconds());
 	}
 }
 
@@ -203,22 +233,23 @@ fn player_movement(
 	time: Res<Time>,
 	mut player_query: Query<(&mut Transform, &mut PlayerMovement)>,
 	mut shadow_query: Query<&mut Transform, (With<Shadow>, Without<PlayerMovement>)>,
-	land_query: Query<(&Transform, &Land), (Without<Shadow>, Without<PlayerMovement>)>,
+	ground: Ground,
+	level: Res<CurrentLevel>,
 ) {
 	for (mut player_transform, mut player_movement) in player_query.iter_mut() {
 		let target = player_movement.cart_transform + player_movement.velocity - iso_to_cart(Vec2::new(0.0, 32.0));
-		let mut grounded = false;
-		for (transform, land) in land_query.iter() {
-			if (target.x - transform.translation.x).abs() < land.half_size.x
-			&& (target.y - transform.translation.y).abs() < land.half_size.y {
-				grounded = true;
-				break;
-			}
+		let grounded = ground.on_land(target);
+
+		// Cliffs stop the player, though a jump gets up higher ones
+		let climb = ground.height_at(target) - ground.height_at(player_movement.cart_transform - iso_to_cart(Vec2::new(0.0, 32.0)));
+		let max_climb = if player_movement.airborne() {JUMP_CLIMB} else {MAX_STEP};
+		if climb > max_climb {
+			player_movement.velocity = Vec2::ZERO;
 		}
 
 		if !grounded && player_movement.airtime.finished() {
 			//player_movement.velocity = -player_movement.velocity;
-			let cart_loc = Vec2::new(750.0, -50.0)*REVERT;
+			let cart_loc = level.0.player_spawn;
 			let iso_loc = cart_to_iso(cart_loc);
 			player_transform.translation.x = iso_loc.x;
 			player_transform.translation.y = iso_loc.y;
@@ -284,15 +315,14 @@ fn player_bell(
 	keyboard: Res<ButtonInput<KeyCode>>,
 	mut player_query: Query<(&Transform, &mut PlayerMovement)>,
 	mut goat_query: Query<&mut GoatMovement>,
-	mut goatbird_query: Query<&mut GoatbirdMovement>,
-	spitter_query: Query<(Entity, &Spitter)>,
-	mut spit_query: Query<(Entity, &mut Spit), Without<Spitter>>,
 	mut ev_w_bell: EventWriter<BellEvent>,
 	time: Res<Time>,
-	mut commands: Commands,
 	audio: Res<Audio>,
 	asset_server: Res<AssetServer>,
 	volume: Res<Volume>,
+	spatial_index: Res<SpatialIndex>,
+	mut transitions: EventWriter<GoatTransition>,
+	mut game_rng: ResMut<GameRng>,
 ) {
 	let e = keyboard.just_pressed(KeyCode::KeyE);
 	let k = keyboard.just_pressed(KeyCode::KeyK);
@@ -316,7 +346,7 @@ fn player_bell(
 		for (transform, mut player_movement) in player_query.iter_mut() {
 			let selected_bell = player_movement.selected_bell as usize;
 			if player_movement.bell_cooldown[selected_bell].finished() {
-				let rnd = rand::random::<f32>();
+				let rnd = game_rng.random(RngStream::Sounds);
 					let path = if rnd < 0.3 {
 						"sfx/bell.ogg"
 					} else if rnd < 0.6 {
@@ -333,73 +363,43 @@ fn player_bell(
 					3 => 500.0,
 					_ => 500.0,
 				};
-				ev_w_bell.send(BellEvent{location: transform.translation.xy(), selected_bell: selected_bell});
-				for mut goat_movement in goat_query.iter_mut() {
+				ev_w_bell.send(BellEvent{location: transform.translation.xy(), cart_location: player_movement.cart_transform, range: max_distance, selected_bell: selected_bell});
+				let player_loc = player_movement.cart_transform;
+				for (goat_entity, _) in spatial_index.goats.nearby(player_loc, max_distance * Breed::max_bell_sensitivity()) {
+					let Ok(mut goat_movement) = goat_query.get_mut(goat_entity) else {continue};
+					let sensitivity = goat_movement.breed.profile().bell_sensitivity;
 					let g_t_p = player_movement.cart_transform - goat_movement.cart_transform;
 					let distance = g_t_p.length();
-					if distance > 0.0 && distance < max_distance {
-						goat_movement.grabbed = false;
-						goat_movement.move_timer.reset();
-						goat_movement.move_timer.tick(Duration::from_millis((rand::random::<f32>() * 2500.0) as u64));
+					// Penned goats have nowhere else to be, carried goats can't get away and panicking goats aren't listening
+					if distance > 0.0 && distance < max_distance * sensitivity && goat_movement.in_play() && !matches!(goat_movement.state(), GoatState::Panicked{..} | GoatState::Grabbed) {
+						if selected_bell == 3 {
+							goat_movement.set_state(goat_entity, GoatState::Boosted, &mut transitions);
+						} else {
+							goat_movement.set_state(goat_entity, GoatState::Fleeing, &mut transitions);
+							// Up to 2.5s into a 3s walk, in proportion for other breeds
+							goat_movement.skip_time(game_rng.random(RngStream::Bells) * 2.5 / 3.0);
+						}
 						match selected_bell {
 							4 => {
 								let gp_v = g_t_p.normalize();
-								let theta_offset = (rand::random::<f32>() * -20.0).to_radians();
+								let theta_offset = (game_rng.random(RngStream::Bells) * -20.0).to_radians();
 								goat_movement.velocity = gp_v.rotate(Vec2::from_angle((90.0 + theta_offset).to_radians()))
 							}
-							3 => {
-								goat_movement.boost_timer.reset();
-							}
+							3 => (),
 							_ => {
 								let mut gp_v = g_t_p.normalize();
 								if selected_bell == 1 {gp_v = -gp_v};
 								let gv_v = goat_movement.velocity.normalize();
 								let theta = gv_v.angle_between(gp_v);
 								//println!("Theta: {}", theta.to_degrees());
-								let range = distance/max_distance;
-								let theta_offset = ((rand::random::<f32>() - 0.5) * range * 60.0).to_radians();
-								goat_movement.velocity = goat_movement.velocity.rotate(Vec2::from_angle(theta + theta_offset));
+								let range = distance/(max_distance * sensitivity);
+								let theta_offset = ((game_rng.random(RngStream::Bells) - 0.5) * range * 60.0).to_radians();
+								// Stubborn goats only turn part of the way
+								goat_movement.velocity = goat_movement.velocity.rotate(Vec2::from_angle(theta * sensitivity.min(1.0) + theta_offset));
 							}					
 						}
 					}
 				}
-				for mut goatbird_movement in goatbird_query.iter_mut() {
-					if !goatbird_movement.scared {
-						let g_t_p = player_movement.cart_transform - goatbird_movement.cart_transform;
-						let distance = g_t_p.length();
-						if distance > 0.0 && distance < max_distance && selected_bell < 3 {
-							let mut gp_v = g_t_p.normalize();
-							let gv_v = goatbird_movement.velocity.normalize();
-							let theta = gv_v.angle_between(gp_v);
-							let range = distance/max_distance;
-							let theta_offset = ((rand::random::<f32>() - 0.5) * range * 60.0).to_radians();
-							goatbird_movement.velocity = -goatbird_movement.velocity.rotate(Vec2::from_angle(theta + theta_offset));
-							goatbird_movement.feeding = false;
-							goatbird_movement.scared = true;
-						}
-					}
-				}
-				for (entity, spitter) in spitter_query.iter() {
-					let g_t_p = player_movement.cart_transform - spitter.cart_transform;
-					let distance = g_t_p.length();
-					if distance > 0.0 && distance < max_distance && selected_bell < 3 {
-						commands.entity(entity).despawn_recursive();
-					}
-				}
-				for (entity, mut spit) in spit_query.iter_mut() {
-					let g_t_p = player_movement.cart_transform - spit.cart_transform;
-					let distance = g_t_p.length();
-					if distance > 0.0 && distance < max_distance && selected_bell < 3 {
-						if selected_bell == 1 {
-							if !spit.scared {
-								spit.velocity = -g_t_p.normalize();
-								spit.scared = true;
-							}
-						} else {
-							commands.entity(entity).despawn_recursive();
-						}
-					}
-				}
 			}
 		}
 	}
@@ -409,7 +409,7 @@ fn sort_z_layer(
 	mut sortable_query: Query<&mut Transform, Or<(With<PlayerMovement>, With<GoatMovement>)>>,
 ) {
 	for mut transform in sortable_query.iter_mut() {
-		transform.translation.z = (1.0 - (transform.translation.y + ORTHO.y/2.0) / ORTHO.y) + 600.0;
+		transform.translation.z = depth_z(transform.translation.y);
 	}
 }
 
@@ -417,7 +417,7 @@ fn sort_sprite_flip(
 	mut player_query: Query<(&mut Sprite, &PlayerMovement)>,
 	mut shadow_query: Query<&mut Sprite, (With<Shadow>, Without<PlayerMovement>)>,
 	mut goat_query: Query<(&mut Sprite,  &GoatMovement), (Without<Shadow>, Without<PlayerMovement>)>,
-	mut goatbird_query: Query<(&mut Sprite,  &GoatbirdMovement), (Without<Shadow>, Without<PlayerMovement>, Without<GoatMovement>)>,
+	mut goatbird_query: Query<(&mut Sprite, &Enemy, &GoatbirdMovement), (Without<Shadow>, Without<PlayerMovement>, Without<GoatMovement>)>,
 ) {
 	for (mut player_sprite, player_movement) in player_query.iter_mut() {
 		for mut shadow_sprite in shadow_query.iter_mut() {
@@ -441,9 +441,9 @@ fn sort_sprite_flip(
 		}
 	}
 
-	for (mut goatbird_sprite, goatbird_movement) in goatbird_query.iter_mut() {
+	for (mut goatbird_sprite, enemy, goatbird_movement) in goatbird_query.iter_mut() {
 		if !goatbird_movement.feeding {
-			let x_comp = cart_to_iso(goatbird_movement.velocity).x;
+			let x_comp = cart_to_iso(enemy.velocity).x;
 			if x_comp > 0.0 {
 				goatbird_sprite.flip_x = true;
 			} else if x_comp < 0.0 {
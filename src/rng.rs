@@ -0,0 +1,154 @@
+// Rng module, for seeded gameplay randomness
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{helper::GameState, level::LevelSelect, setup::{GameMode, ProgressTracker}};
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.insert_resource(GameRng::new(starting_seed()))
+			.add_systems(OnEnter(GameState::Game), (
+				reseed_rng.before(LevelSelect),
+			))
+		;
+	}
+}
+
+// Set GOAT_SEED to get the same endless maps and the same starting streams
+// for every level, otherwise every launch picks its own. It isn't a full
+// replay: systems step by the variable frame time, so how many numbers get
+// drawn and when still depends on the frame rate.
+const SEED_VAR: &str = "GOAT_SEED";
+
+fn starting_seed() -> u64 {
+	let seed = std::env::var(SEED_VAR).ok()
+		.and_then(|seed| seed.parse().ok())
+		.unwrap_or_else(rand::random);
+	info!("Rng seed: {}", seed);
+	seed
+}
+
+// Separate streams, so one system drawing more numbers doesn't shift
+// what every other system gets
+#[derive(Clone, Copy)]
+pub enum RngStream {
+	// Goat turning and slipping
+	Goats,
+	// Which bleat or bell sound plays
+	Sounds,
+	// Bell timing and angle jitter
+	Bells,
+	// Where goats, goatbirds and spitters appear
+	Spawns,
+	// Enemy aim
+	Enemies,
+	// Picks the seed for a new endless run
+	Endless,
+}
+
+const STREAM_COUNT: usize = 6;
+
+#[derive(Resource)]
+pub struct GameRng{
+	pub seed: u64,
+	streams: [StdRng; STREAM_COUNT],
+}
+
+impl GameRng {
+	pub fn new(
+		seed: u64,
+	) -> GameRng {
+		GameRng{
+			seed,
+			streams: streams(seed, 0),
+		}
+	}
+
+	pub fn stream(
+		&mut self,
+		stream: RngStream,
+	) -> &mut StdRng {
+		&mut self.streams[stream as usize]
+	}
+
+	// Shorthand for a number in [0, 1)
+	pub fn random(
+		&mut self,
+		stream: RngStream,
+	) -> f32 {
+		self.stream(stream).gen()
+	}
+}
+
+fn streams(
+	seed: u64,
+	level_key: u64,
+) -> [StdRng; STREAM_COUNT] {
+	std::array::from_fn(|i| StdRng::seed_from_u64(
+		seed.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+			.wrapping_add(level_key)
+			.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+			.wrapping_add(i as u64)
+	))
+}
+
+// Each level starts from streams that only depend on the seed and which
+// level it is, so a retry starts out drawing the same numbers
+fn reseed_rng(
+	mut game_rng: ResMut<GameRng>,
+	progress_tracker: Res<ProgressTracker>,
+) {
+	let level_key = match progress_tracker.mode {
+		GameMode::Tutorial => 0,
+		GameMode::Campaign(i) => 1 + i as u64,
+		GameMode::Endless(i) => progress_tracker.endless_seed.wrapping_add(i as u64) | 1 << 63,
+	};
+	game_rng.streams = streams(game_rng.seed, level_key);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn draws(
+		game_rng: &mut GameRng,
+		stream: RngStream,
+	) -> Vec<f32> {
+		(0..8).map(|_| game_rng.random(stream)).collect()
+	}
+
+	#[test]
+	fn same_seed_same_numbers() {
+		let mut first = GameRng::new(42);
+		let mut second = GameRng::new(42);
+		assert_eq!(draws(&mut first, RngStream::Goats), draws(&mut second, RngStream::Goats));
+		assert_eq!(draws(&mut first, RngStream::Spawns), draws(&mut second, RngStream::Spawns));
+		assert_ne!(draws(&mut GameRng::new(43), RngStream::Goats), draws(&mut GameRng::new(42), RngStream::Goats));
+	}
+
+	#[test]
+	fn streams_dont_share_numbers() {
+		let mut game_rng = GameRng::new(42);
+		let goats = draws(&mut game_rng, RngStream::Goats);
+		// Drawing from one stream leaves the others where they were
+		draws(&mut game_rng, RngStream::Sounds);
+		let mut fresh = GameRng::new(42);
+		draws(&mut fresh, RngStream::Goats);
+		assert_eq!(draws(&mut game_rng, RngStream::Spawns), draws(&mut fresh, RngStream::Spawns));
+		assert_ne!(goats, draws(&mut GameRng::new(42), RngStream::Sounds));
+	}
+
+	#[test]
+	fn retry_starts_the_same_streams() {
+		let mut game_rng = GameRng::new(42);
+		game_rng.streams = streams(game_rng.seed, 3);
+		let first = draws(&mut game_rng, RngStream::Bells);
+		game_rng.streams = streams(game_rng.seed, 3);
+		assert_eq!(first, draws(&mut game_rng, RngStream::Bells));
+		game_rng.streams = streams(game_rng.seed, 4);
+		assert_ne!(first, draws(&mut game_rng, RngStream::Bells));
+	}
+}
@@ -14,6 +14,7 @@ impl Plugin for SetupPlugin {
 			.insert_resource(ProgressTracker{
 				mode:GameMode::Tutorial,
 				max_campaign: 0,
+				endless_seed: 0,
 				win_timer: Timer::from_seconds(2.0, TimerMode::Once),
 				lose_timer: Timer::from_seconds(2.0, TimerMode::Once),
 			})
@@ -49,6 +50,9 @@ impl Plugin for SetupPlugin {
 #[derive(Event)]
 pub struct BellEvent{
 	pub location: Vec2,
+	// Where the player rang it and how far it carries, for anything that reacts to bells
+	pub cart_location: Vec2,
+	pub range: f32,
 	pub selected_bell: usize,
 }
 
@@ -63,6 +67,8 @@ pub enum GameMode {
 pub struct ProgressTracker{
 	pub mode: GameMode,
 	pub max_campaign: usize,
+	// Each endless round generates its map from this seed plus the round number
+	pub endless_seed: u64,
 	pub win_timer: Timer,
 	pub lose_timer: Timer,
 }
@@ -0,0 +1,145 @@
+// Spatial module, for finding goats and enemies near a point without checking every one
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{enemy::Enemy, goat::GoatMovement, helper::GameState};
+
+pub struct SpatialPlugin;
+
+impl Plugin for SpatialPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.insert_resource(SpatialIndex{
+				goats: SpatialHash::new(CELL_SIZE),
+				enemies: SpatialHash::new(CELL_SIZE),
+			})
+			.add_systems(PreUpdate, (
+				build_spatial_index,
+			).run_if(in_state(GameState::Game)))
+		;
+	}
+}
+
+// Cart size of a cell, about two goats across
+const CELL_SIZE: f32 = 64.0;
+
+// Entities bucketed by cart position. Positions are from the start of the
+// frame, so treat results as candidates and check the real distance.
+pub struct SpatialHash{
+	cell_size: f32,
+	cells: HashMap<IVec2, Vec<(Entity, Vec2)>>,
+	min_cell: IVec2,
+	max_cell: IVec2,
+}
+
+impl SpatialHash {
+	pub fn new(
+		cell_size: f32,
+	) -> SpatialHash {
+		SpatialHash{
+			cell_size,
+			cells: HashMap::new(),
+			min_cell: IVec2::MAX,
+			max_cell: IVec2::MIN,
+		}
+	}
+
+	fn cell(
+		&self,
+		point: Vec2,
+	) -> IVec2 {
+		(point / self.cell_size).floor().as_ivec2()
+	}
+
+	pub fn clear(
+		&mut self,
+	) {
+		// Keep the buckets around, most cells are reused next frame
+		for entries in self.cells.values_mut() {
+			entries.clear();
+		}
+		self.min_cell = IVec2::MAX;
+		self.max_cell = IVec2::MIN;
+	}
+
+	pub fn insert(
+		&mut self,
+		entity: Entity,
+		point: Vec2,
+	) {
+		let cell = self.cell(point);
+		self.min_cell = self.min_cell.min(cell);
+		self.max_cell = self.max_cell.max(cell);
+		self.cells.entry(cell).or_default().push((entity, point));
+	}
+
+	// Everything in the cells the circle touches
+	pub fn nearby(
+		&self,
+		point: Vec2,
+		radius: f32,
+	) -> impl Iterator<Item = (Entity, Vec2)> + '_ {
+		let min = self.cell(point - Vec2::splat(radius)).max(self.min_cell);
+		let max = self.cell(point + Vec2::splat(radius)).min(self.max_cell);
+		(min.x..=max.x)
+			.flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+			.filter_map(|cell| self.cells.get(&cell))
+			.flat_map(|entries| entries.iter().copied())
+	}
+
+	// Closest entity that passes the filter, searching outwards a ring of cells at a time
+	pub fn nearest(
+		&self,
+		point: Vec2,
+		filter: impl Fn(Entity) -> bool,
+	) -> Option<(Entity, Vec2)> {
+		if self.min_cell.cmpgt(self.max_cell).any() {
+			return None;
+		}
+		let center = self.cell(point);
+		let rings = (center - self.min_cell).abs().max((self.max_cell - center).abs()).max_element();
+		let mut best: Option<(Entity, Vec2, f32)> = None;
+		for ring in 0..=rings {
+			// Nothing further out can beat a match closer than the ring's inner edge
+			if best.is_some_and(|(_, _, distance)| distance < (ring - 1).max(0) as f32 * self.cell_size) {
+				break;
+			}
+			for x in -ring..=ring {
+				for y in -ring..=ring {
+					if x.abs() != ring && y.abs() != ring {
+						continue;
+					}
+					let Some(entries) = self.cells.get(&(center + IVec2::new(x, y))) else {continue};
+					for &(entity, position) in entries.iter() {
+						let distance = position.distance(point);
+						if best.is_none_or(|(_, _, best)| distance < best) && filter(entity) {
+							best = Some((entity, position, distance));
+						}
+					}
+				}
+			}
+		}
+		best.map(|(entity, position, _)| (entity, position))
+	}
+}
+
+#[derive(Resource)]
+pub struct SpatialIndex{
+	pub goats: SpatialHash,
+	pub enemies: SpatialHash,
+}
+
+fn build_spatial_index(
+	mut spatial_index: ResMut<SpatialIndex>,
+	goat_query: Query<(Entity, &GoatMovement)>,
+	enemy_query: Query<(Entity, &Enemy)>,
+) {
+	let spatial_index = spatial_index.as_mut();
+	spatial_index.goats.clear();
+	for (entity, goat) in goat_query.iter() {
+		spatial_index.goats.insert(entity, goat.cart_transform);
+	}
+	spatial_index.enemies.clear();
+	for (entity, enemy) in enemy_query.iter() {
+		spatial_index.enemies.insert(entity, enemy.cart_transform);
+	}
+}
@@ -0,0 +1,296 @@
+// Terrain module, for drawing level land from iso tile sprites
+use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}}};
+
+use crate::{elevation::MAX_STEP, helper::{cart_to_iso, depth_z, iso_to_cart, GameState}, land::LandShape, level::{CurrentLevel, LevelSelect}, setup::ORTHO};
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(Startup, (
+				build_tile_images,
+			))
+			.add_systems(OnEnter(GameState::Game), (
+				terrain_setup.after(LevelSelect),
+			))
+		;
+	}
+}
+
+// Cart size of one tile in scaled space, a tile is drawn wherever its centre is on land
+pub const TILE: f32 = 24.0;
+// How far cliff faces hang below exposed edges, in iso pixels
+const CLIFF_DEPTH: f32 = 40.0;
+// Raised ground gets as many of these stacked down its sides as it needs
+const BAND_DEPTH: f32 = 8.0;
+const RIM_WIDTH: f32 = 3.0;
+
+// Cliffs sit just under the tile tops, and both sit below goats and the
+// player (600 to 601) and the fence overlay (870)
+pub const TERRAIN_Z: f32 = 450.0;
+
+// Neighbour mask bits, set when there is no ground on that side of a tile
+const EDGE_NEG_X: usize = 1;
+const EDGE_POS_X: usize = 2;
+const EDGE_NEG_Y: usize = 4;
+const EDGE_POS_Y: usize = 8;
+
+// Generated tile art, tops are indexed by neighbour mask
+#[derive(Resource)]
+pub struct TerrainTiles{
+	grass: Vec<Handle<Image>>,
+	dirt: Vec<Handle<Image>>,
+	cliff_pos_x: Handle<Image>,
+	cliff_neg_y: Handle<Image>,
+	band_pos_x: Handle<Image>,
+	band_neg_y: Handle<Image>,
+}
+
+fn build_tile_images(
+	mut commands: Commands,
+	mut images: ResMut<Assets<Image>>,
+) {
+	let grass = Color::rgb(0.42, 0.6, 0.3);
+	let dirt = Color::rgb(0.62, 0.5, 0.32);
+	let tops = |base: Color, images: &mut Assets<Image>| {
+		(0..16)
+			.map(|mask| images.add(tile_image(Vec2::new(TILE * 2.0, TILE), |iso| top_pixel(iso, mask, base))))
+			.collect::<Vec<_>>()
+	};
+	let (pos_x, neg_y) = (Color::rgb(0.35, 0.27, 0.2), Color::rgb(0.45, 0.36, 0.26));
+	let cliff_size = Vec2::new(TILE, TILE/2.0 + CLIFF_DEPTH);
+	let band_size = Vec2::new(TILE, TILE/2.0 + BAND_DEPTH);
+	commands.insert_resource(TerrainTiles{
+		grass: tops(grass, &mut images),
+		dirt: tops(dirt, &mut images),
+		cliff_pos_x: images.add(tile_image(cliff_size, |iso| cliff_pixel(iso, 1.0, CLIFF_DEPTH, true, pos_x))),
+		cliff_neg_y: images.add(tile_image(cliff_size, |iso| cliff_pixel(iso, -1.0, CLIFF_DEPTH, true, neg_y))),
+		band_pos_x: images.add(tile_image(band_size, |iso| cliff_pixel(iso, 1.0, BAND_DEPTH, false, pos_x))),
+		band_neg_y: images.add(tile_image(band_size, |iso| cliff_pixel(iso, -1.0, BAND_DEPTH, false, neg_y))),
+	});
+}
+
+// Pixel callback gets the pixel centre in iso space, relative to the image centre with y up
+pub fn tile_image(
+	size: Vec2,
+	pixel: impl Fn(Vec2) -> Option<Color>,
+) -> Image {
+	let (width, height) = (size.x as u32, size.y as u32);
+	let mut data = Vec::with_capacity((width * height * 4) as usize);
+	for y in 0..height {
+		for x in 0..width {
+			let iso = Vec2::new(x as f32 + 0.5 - size.x/2.0, size.y/2.0 - y as f32 - 0.5);
+			data.extend_from_slice(&pixel(iso).map_or([0; 4], |color| color.as_rgba_u8()));
+		}
+	}
+	Image::new(
+		Extent3d{width, height, depth_or_array_layers: 1},
+		TextureDimension::D2,
+		data,
+		TextureFormat::Rgba8UnormSrgb,
+		RenderAssetUsages::RENDER_WORLD,
+	)
+}
+
+// Small fixed speckle so large areas of ground don't look flat
+fn speckle(
+	iso: Vec2,
+) -> f32 {
+	let (x, y) = (iso.x.floor() as i32, iso.y.floor() as i32);
+	(x.wrapping_mul(73).wrapping_add(y.wrapping_mul(151))).rem_euclid(7) as f32 * 0.012
+}
+
+fn top_pixel(
+	iso: Vec2,
+	mask: usize,
+	base: Color,
+) -> Option<Color> {
+	let cart = iso_to_cart(iso);
+	let half = TILE/2.0 + 0.5;
+	if cart.x.abs() > half || cart.y.abs() > half {
+		return None;
+	}
+	let rim = (mask & EDGE_NEG_X != 0 && cart.x < RIM_WIDTH - half)
+		|| (mask & EDGE_POS_X != 0 && cart.x > half - RIM_WIDTH)
+		|| (mask & EDGE_NEG_Y != 0 && cart.y < RIM_WIDTH - half)
+		|| (mask & EDGE_POS_Y != 0 && cart.y > half - RIM_WIDTH);
+	let shade = if rim {0.75} else {1.0 - speckle(iso)};
+	Some(Color::rgb(base.r() * shade, base.g() * shade, base.b() * shade))
+}
+
+// Face hanging under the +x edge (side 1.0) or the -y edge (side -1.0) of a
+// tile, with a grass lip along the top when lip is set
+fn cliff_pixel(
+	iso: Vec2,
+	side: f32,
+	depth: f32,
+	lip: bool,
+	base: Color,
+) -> Option<Color> {
+	// Back to coordinates relative to the tile centre
+	let x = iso.x + side * TILE/2.0;
+	let y = iso.y - (TILE/2.0 + depth)/2.0;
+	let top = -TILE/2.0 + side * x/2.0;
+	if y > top + 0.5 || y < top - depth {
+		return None;
+	}
+	if lip && y > top - RIM_WIDTH {
+		return Some(Color::rgb(0.3, 0.45, 0.22));
+	}
+	let shade = 1.0 - speckle(iso);
+	Some(Color::rgb(base.r() * shade, base.g() * shade, base.b() * shade))
+}
+
+fn terrain_setup(
+	mut commands: Commands,
+	terrain_tiles: Res<TerrainTiles>,
+	level: Res<CurrentLevel>,
+) {
+	if !level.0.draw_land {
+		return;
+	}
+
+	commands.spawn((SpriteBundle {
+		transform: Transform::from_xyz(0.0, 0.0, TERRAIN_Z - 10.0),
+		sprite: Sprite {
+			color: Color::rgb(0.45, 0.62, 0.72),
+			custom_size: Some(ORTHO),
+			..default()
+		},
+		..default()
+		},
+	));
+
+	let land: Vec<&LandShape> = level.0.land.iter().chain(level.0.islands.iter()).collect();
+	let goals: Vec<&LandShape> = level.0.goals.iter().map(|pen| &pen.shape).collect();
+	commands.spawn(SpatialBundle::default()).with_children(|parent| {
+		build_terrain(parent, &terrain_tiles, &land, &goals);
+	});
+}
+
+// Tiles covering the shapes, with edges and cliffs wherever the ground ends.
+// Positions are absolute, so move the parent to move the terrain with it.
+pub fn build_terrain(
+	parent: &mut ChildBuilder,
+	terrain_tiles: &TerrainTiles,
+	land: &[&LandShape],
+	goals: &[&LandShape],
+) {
+	let cell_center = |cell: IVec2| (cell.as_vec2() + 0.5) * TILE;
+	// None off the ground, otherwise whether the tile is in a goal
+	let ground = |cell: IVec2| {
+		let point = cell_center(cell);
+		if goals.iter().any(|goal| goal.contains(point)) {
+			Some(true)
+		} else if land.iter().any(|shape| shape.contains(point)) {
+			Some(false)
+		} else {
+			None
+		}
+	};
+
+	let corners: Vec<Vec2> = land.iter().chain(goals.iter()).flat_map(|shape| shape.points()).collect();
+	if corners.is_empty() {
+		return;
+	}
+	let min = corners.iter().fold(Vec2::INFINITY, |min, point| min.min(*point));
+	let max = corners.iter().fold(Vec2::NEG_INFINITY, |max, point| max.max(*point));
+	let (min_cell, max_cell) = ((min / TILE).floor().as_ivec2(), (max / TILE).ceil().as_ivec2());
+
+	let tile_offset = (TILE/2.0 + CLIFF_DEPTH)/2.0;
+	for x in min_cell.x..max_cell.x {
+		for y in min_cell.y..max_cell.y {
+			let cell = IVec2::new(x, y);
+			let Some(in_goal) = ground(cell) else {continue};
+			let mut mask = 0;
+			for (offset, edge) in [(IVec2::NEG_X, EDGE_NEG_X), (IVec2::X, EDGE_POS_X), (IVec2::NEG_Y, EDGE_NEG_Y), (IVec2::Y, EDGE_POS_Y)] {
+				if ground(cell + offset).is_none() {
+					mask |= edge;
+				}
+			}
+
+			let iso_loc = cart_to_iso(cell_center(cell));
+			let texture = if in_goal {&terrain_tiles.dirt[mask]} else {&terrain_tiles.grass[mask]};
+			spawn_tile(parent, texture, iso_loc, TERRAIN_Z + 1.0);
+			// Only the two edges facing the camera show a cliff
+			if mask & EDGE_POS_X != 0 {
+				spawn_tile(parent, &terrain_tiles.cliff_pos_x, iso_loc + Vec2::new(TILE/2.0, -tile_offset), TERRAIN_Z);
+			}
+			if mask & EDGE_NEG_Y != 0 {
+				spawn_tile(parent, &terrain_tiles.cliff_neg_y, iso_loc + Vec2::new(-TILE/2.0, -tile_offset), TERRAIN_Z);
+			}
+		}
+	}
+}
+
+// Raised tiles over a shape, each lifted by the ground height at its centre.
+// Cliff bands run down exposed edges to the ground next to them. Tiles sort
+// with goats and the player by the back of their footprint, so whatever
+// stands on a tile draws over it and whatever is behind draws under it.
+pub fn build_raised_terrain(
+	parent: &mut ChildBuilder,
+	terrain_tiles: &TerrainTiles,
+	shape: &LandShape,
+	height_at: impl Fn(Vec2) -> f32,
+) {
+	for center in tile_centers(shape) {
+		let height = height_at(center);
+		let drop = |offset: Vec2| height - height_at(center + offset * TILE);
+		let mut mask = 0;
+		for (offset, edge) in [(Vec2::NEG_X, EDGE_NEG_X), (Vec2::X, EDGE_POS_X), (Vec2::NEG_Y, EDGE_NEG_Y), (Vec2::Y, EDGE_POS_Y)] {
+			if drop(offset) > MAX_STEP {
+				mask |= edge;
+			}
+		}
+
+		let ground_loc = cart_to_iso(center);
+		let iso_loc = ground_loc + Vec2::new(0.0, height);
+		// Sprites sit 32 above the feet, so match that when sorting
+		let z = depth_z(ground_loc.y + TILE/2.0 + 32.0) - 0.001;
+		spawn_tile(parent, &terrain_tiles.grass[mask], iso_loc, z);
+		// Even small steps on a ramp need filling in, or the ground shows through
+		let band_offset = (TILE/2.0 + BAND_DEPTH)/2.0;
+		for (side, texture, offset) in [(1.0, &terrain_tiles.band_pos_x, Vec2::X), (-1.0, &terrain_tiles.band_neg_y, Vec2::NEG_Y)] {
+			let bands = (drop(offset) / BAND_DEPTH).ceil().max(0.0) as usize;
+			for band in 0..bands {
+				let band_loc = iso_loc + Vec2::new(side * TILE/2.0, -band_offset - band as f32 * BAND_DEPTH);
+				spawn_tile(parent, texture, band_loc, z - 0.0005);
+			}
+		}
+	}
+}
+
+// Centres of the tiles that fall inside a shape
+pub fn tile_centers(
+	shape: &LandShape,
+) -> Vec<Vec2> {
+	let points = shape.points();
+	let min = points.iter().fold(Vec2::INFINITY, |min, point| min.min(*point));
+	let max = points.iter().fold(Vec2::NEG_INFINITY, |max, point| max.max(*point));
+	let (min_cell, max_cell) = ((min / TILE).floor().as_ivec2(), (max / TILE).ceil().as_ivec2());
+	let mut centers = Vec::new();
+	for x in min_cell.x..max_cell.x {
+		for y in min_cell.y..max_cell.y {
+			let center = (IVec2::new(x, y).as_vec2() + 0.5) * TILE;
+			if shape.contains(center) {
+				centers.push(center);
+			}
+		}
+	}
+	centers
+}
+
+fn spawn_tile(
+	parent: &mut ChildBuilder,
+	texture: &Handle<Image>,
+	iso_loc: Vec2,
+	z: f32,
+) {
+	parent.spawn((SpriteBundle {
+		transform: Transform::from_xyz(iso_loc.x, iso_loc.y, z),
+		texture: texture.clone(),
+		..default()
+		},
+	));
+}
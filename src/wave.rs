@@ -0,0 +1,520 @@
+// Wave module, for scripted goat and enemy spawns
+use std::f32::consts::TAU;
+
+use bevy::{asset::{io::Reader, ron, AssetLoader, AsyncReadExt, LoadContext}, ecs::system::SystemParam, prelude::*, utils::BoxedFuture};
+use rand::{distributions::{Distribution, WeightedIndex}, seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{enemy::{EnemyKind, EnemySpawner, SpitterProfile}, goat::{pick_breed, spawn_goat, Breed, GoatMovement, GoatNumbers, GoatState, GoatTransition}, helper::{iso_to_cart, GameState}, land::{Ground, LandShape}, level::{CurrentLevel, LevelSelect}, rng::{GameRng, RngStream}, setup::{GameMode, ProgressTracker, REVERT}};
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_asset::<WaveScript>()
+			.init_asset_loader::<WaveScriptLoader>()
+			.add_systems(OnEnter(GameState::Game), (
+				wave_setup.after(LevelSelect),
+			))
+			.add_systems(Update, (
+				run_waves,
+			).run_if(in_state(GameState::Game)))
+		;
+	}
+}
+
+// Shortest repeat a wave file can ask for, anything near zero would start
+// an entry over and over within one frame
+const MIN_REPEAT: f32 = 0.1;
+
+// Timed spawns for one campaign day or endless tier, loaded from
+// assets/waves/*.wave.ron. Every entry runs on its own clock.
+#[derive(Asset, TypePath, Deserialize, Serialize, Clone, Default)]
+pub struct WaveScript {
+	pub entries: Vec<WaveEntry>,
+}
+
+// Spawns count of spawn, every seconds apart, starting at seconds in and
+// starting over every repeat seconds when given
+#[derive(Deserialize, Serialize, Clone)]
+pub struct WaveEntry {
+	// Seconds after the conditions are met, or after the level starts without any
+	pub at: f32,
+	pub spawn: Spawnable,
+	#[serde(default = "default_count")]
+	pub count: usize,
+	// Seconds between each of the count
+	#[serde(default)]
+	pub every: f32,
+	// Starts over this many seconds after it last started, runs once when None.
+	// At least MIN_REPEAT.
+	#[serde(default)]
+	pub repeat: Option<f32>,
+	#[serde(default)]
+	pub region: SpawnRegion,
+	// Goats only, picked from the level's breed weights when None
+	#[serde(default)]
+	pub breed: Option<Breed>,
+	// Spitters only, how they pick a goat, aim and fire,
+	// e.g. (target: Isolated, lead: 1.0, spread: 5.0, burst: Some(3), spit_speed: 300.0)
+	#[serde(default)]
+	pub spitter: SpitterProfile,
+	// All of these have to hold before the entry starts
+	#[serde(default)]
+	pub after: Vec<WaveCondition>,
+	// Each run of count only happens this often, for random events
+	#[serde(default = "default_chance")]
+	pub chance: f32,
+}
+
+fn default_count() -> usize {
+	1
+}
+
+fn default_chance() -> f32 {
+	1.0
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum Spawnable {
+	// From the level's goat count
+	Goat,
+	// Extra goats on top of the level's count. Strays are kids wandering in
+	// from the edge of the land, Rescued brings back a goat that was lost and
+	// Bonus is a plain group at the goat spawns.
+	Strays,
+	Rescued,
+	Bonus,
+	Goatbird,
+	Spitter,
+	// Brings in every arm the level has, once
+	Arm,
+	// Digs in somewhere on the land outside the pens, or at the region when it's At
+	Burrower,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default)]
+pub enum SpawnRegion {
+	// The level's goat spawns, goatbird line or spitter spawns
+	#[default]
+	Level,
+	// Unscaled cart position, like the level files
+	At(Vec2),
+	// Goats only, one of the level's goat spawns by index, even while closed
+	GoatSpawn(usize),
+}
+
+// Level data for a place goats walk in from
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct GoatSpawn{
+	pub point: Vec2,
+	// Chance of being picked against the other open spawns, 1.0 by default
+	#[serde(default = "default_spawn_weight")]
+	pub weight: f32,
+	// Cart direction new goats set off in, towards the middle of the map when None
+	#[serde(default)]
+	pub facing: Option<Vec2>,
+	// Seconds into the level the spawn opens, and closes again when given
+	#[serde(default)]
+	pub from: f32,
+	#[serde(default)]
+	pub until: Option<f32>,
+	#[serde(default)]
+	pub drift: Option<SpawnDrift>,
+}
+
+// Slides the spawn out to point + offset and back every period seconds
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct SpawnDrift{
+	pub offset: Vec2,
+	pub period: f32,
+}
+
+fn default_spawn_weight() -> f32 {
+	1.0
+}
+
+impl GoatSpawn {
+	pub fn new(
+		point: Vec2,
+	) -> GoatSpawn {
+		GoatSpawn{point, weight: default_spawn_weight(), facing: None, from: 0.0, until: None, drift: None}
+	}
+
+	pub fn is_open(
+		&self,
+		elapsed: f32,
+	) -> bool {
+		elapsed >= self.from && self.until.is_none_or(|until| elapsed < until)
+	}
+
+	pub fn position(
+		&self,
+		elapsed: f32,
+	) -> Vec2 {
+		match self.drift {
+			Some(SpawnDrift{offset, period}) if period > 0.0 => self.point + offset * (1.0 - (elapsed / period * TAU).cos()) / 2.0,
+			_ => self.point,
+		}
+	}
+
+	pub fn facing(
+		&self,
+		elapsed: f32,
+	) -> Vec2 {
+		self.facing
+			.or_else(|| (-self.position(elapsed)).try_normalize())
+			.and_then(|facing| facing.try_normalize())
+			.unwrap_or(Vec2::X)
+	}
+
+	pub fn scaled(
+		&self,
+		scale: f32,
+	) -> GoatSpawn {
+		GoatSpawn{
+			point: self.point * scale,
+			drift: self.drift.map(|drift| SpawnDrift{offset: drift.offset * scale, period: drift.period}),
+			..*self
+		}
+	}
+}
+
+// One of the spawns open right now, by weight
+pub fn pick_goat_spawn<'a>(
+	spawns: &'a [GoatSpawn],
+	elapsed: f32,
+	rng: &mut impl Rng,
+) -> Option<&'a GoatSpawn> {
+	let open: Vec<&GoatSpawn> = spawns.iter().filter(|spawn| spawn.is_open(elapsed)).collect();
+	let index = WeightedIndex::new(open.iter().map(|spawn| spawn.weight)).ok()?;
+	Some(open[index.sample(rng)])
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub enum WaveCondition {
+	// At least this many goats in pens
+	Penned(usize),
+	// At least this many goats spawned so far
+	Spawned(usize),
+	// At least this many goats lost so far
+	Lost(usize),
+	// At least this many goats penned in a row without losing one
+	Streak(usize),
+}
+
+#[derive(Resource)]
+pub struct WaveHandles{
+	pub tutorial: Handle<WaveScript>,
+	pub days: Vec<Handle<WaveScript>>,
+	pub endless: Vec<Handle<WaveScript>>,
+}
+
+impl WaveHandles {
+	// Days and tiers past the last script keep using the last one
+	pub fn for_mode(
+		&self,
+		mode: &GameMode,
+	) -> &Handle<WaveScript> {
+		let pick = |handles: &'_ [Handle<WaveScript>], i: usize| i.min(handles.len() - 1);
+		match *mode {
+			GameMode::Tutorial => &self.tutorial,
+			GameMode::Campaign(i) => &self.days[pick(&self.days, i)],
+			GameMode::Endless(i) => &self.endless[pick(&self.endless, i)],
+		}
+	}
+}
+
+// Least distance in from the outermost corner of the land strays appear
+const STRAY_INSET: f32 = 40.0;
+// Goes without a stray or burrower rather than keep landing off the land or in pens
+const LAND_POINT_TRIES: usize = 8;
+
+// Where each entry of the current script is up to
+struct EntryProgress{
+	// Script time of the next spawn, None until the conditions are met
+	next: Option<f32>,
+	// Script time the current run of count started
+	started: f32,
+	left: usize,
+}
+
+#[derive(Resource)]
+struct WaveRunner{
+	script: WaveScript,
+	elapsed: f32,
+	progress: Vec<EntryProgress>,
+	streak: usize,
+	// Losses seen so far, a new one breaks the streak
+	killed: usize,
+}
+
+fn wave_setup(
+	mut commands: Commands,
+	progress_tracker: Res<ProgressTracker>,
+	wave_handles: Res<WaveHandles>,
+	scripts: Res<Assets<WaveScript>>,
+	mut goat_numbers: ResMut<GoatNumbers>,
+) {
+	goat_numbers.extra = 0;
+	goat_numbers.rescued = 0;
+	let script = scripts.get(wave_handles.for_mode(&progress_tracker.mode)).cloned().unwrap_or_default();
+	commands.insert_resource(WaveRunner{
+		progress: script.entries.iter().map(|entry| EntryProgress{next: None, started: 0.0, left: entry.count}).collect(),
+		script,
+		elapsed: 0.0,
+		streak: 0,
+		killed: 0,
+	});
+}
+
+// Everything a wave entry needs to spawn goats and enemies
+#[derive(SystemParam)]
+struct WaveSpawner<'w, 's> {
+	commands: Commands<'w, 's>,
+	asset_server: Res<'w, AssetServer>,
+	goat_numbers: ResMut<'w, GoatNumbers>,
+	enemy_spawner: EnemySpawner<'w, 's>,
+	level: Res<'w, CurrentLevel>,
+	game_rng: ResMut<'w, GameRng>,
+	ground: Ground<'w, 's>,
+}
+
+fn run_waves(
+	time: Res<Time>,
+	mut wave_runner: ResMut<WaveRunner>,
+	mut spawner: WaveSpawner,
+	goat_query: Query<&GoatMovement>,
+	mut transitions: EventReader<GoatTransition>,
+) {
+	let penned = goat_query.iter().filter(|goat| goat.is_penned()).count();
+	let wave_runner = wave_runner.as_mut();
+	if spawner.goat_numbers.killed > wave_runner.killed {
+		wave_runner.streak = 0;
+	}
+	wave_runner.killed = spawner.goat_numbers.killed;
+	wave_runner.streak += transitions.read().filter(|transition| matches!(transition.to, GoatState::Penned{..})).count();
+	let streak = wave_runner.streak;
+	wave_runner.elapsed += time.delta_seconds();
+	let elapsed = wave_runner.elapsed;
+	for (entry, progress) in wave_runner.script.entries.iter().zip(wave_runner.progress.iter_mut()) {
+		if progress.next.is_none() {
+			let met = entry.after.iter().all(|condition| match *condition {
+				WaveCondition::Penned(goats) => penned >= goats,
+				WaveCondition::Spawned(goats) => spawner.goat_numbers.spawned >= goats,
+				WaveCondition::Lost(goats) => spawner.goat_numbers.killed >= goats,
+				WaveCondition::Streak(goats) => streak >= goats,
+			});
+			if !met {
+				continue;
+			}
+			progress.started = elapsed + entry.at;
+			progress.next = Some(progress.started);
+		}
+		while let Some(next) = progress.next.filter(|&next| next <= elapsed && progress.left > 0) {
+			// Rolled once for each run, and only when needed so other entries draw the same numbers
+			if progress.left == entry.count && entry.chance < 1.0 && spawner.game_rng.random(RngStream::Spawns) >= entry.chance {
+				progress.left = 0;
+			} else {
+				spawner.spawn(entry, elapsed);
+				progress.left -= 1;
+				progress.next = Some(next + entry.every);
+			}
+			if progress.left == 0 {
+				if let Some(repeat) = entry.repeat {
+					progress.started += repeat;
+					progress.next = Some(progress.started);
+					progress.left = entry.count;
+				}
+			}
+		}
+	}
+}
+
+impl<'w, 's> WaveSpawner<'w, 's> {
+	fn spawn(
+		&mut self,
+		entry: &WaveEntry,
+		elapsed: f32,
+	) {
+		let rng = self.game_rng.stream(RngStream::Spawns);
+		let at = match entry.region {
+			SpawnRegion::Level | SpawnRegion::GoatSpawn(_) => None,
+			SpawnRegion::At(cart_loc) => Some(cart_loc * REVERT),
+		};
+		match entry.spawn {
+			Spawnable::Goat | Spawnable::Strays | Spawnable::Rescued | Spawnable::Bonus => {
+				let extra = entry.spawn != Spawnable::Goat;
+				// The level's goat count still caps the total, and only lost goats can be rescued
+				if !extra && self.goat_numbers.spawned >= self.goat_numbers.total + self.goat_numbers.extra {
+					return;
+				}
+				if entry.spawn == Spawnable::Rescued && self.goat_numbers.rescued >= self.goat_numbers.killed {
+					return;
+				}
+				let entry_point = match (at, entry.spawn, entry.region) {
+					(Some(cart_loc), _, _) => Some((cart_loc, (-cart_loc).try_normalize().unwrap_or(Vec2::X))),
+					(None, Spawnable::Strays, SpawnRegion::Level) => land_edge(&self.level.0.land, &self.ground, rng),
+					(None, _, SpawnRegion::GoatSpawn(i)) => self.level.0.goat_spawns.get(i).map(|goat_spawn| (goat_spawn.position(elapsed), goat_spawn.facing(elapsed))),
+					(None, _, _) => pick_goat_spawn(&self.level.0.goat_spawns, elapsed, rng).map(|goat_spawn| (goat_spawn.position(elapsed), goat_spawn.facing(elapsed))),
+				};
+				let Some((cart_loc, facing)) = entry_point else {return};
+				self.goat_numbers.spawned += 1;
+				if extra {
+					self.goat_numbers.extra += 1;
+				}
+				if entry.spawn == Spawnable::Rescued {
+					self.goat_numbers.rescued += 1;
+				}
+				let breed = match entry.spawn {
+					Spawnable::Strays => entry.breed.unwrap_or(Breed::Kid),
+					_ => entry.breed.unwrap_or_else(|| pick_breed(&self.level.0.breeds, rng)),
+				};
+				spawn_goat(&mut self.commands, &self.asset_server, breed, cart_loc, facing);
+			},
+			Spawnable::Goatbird => {
+				let [line_start, line_end] = self.level.0.goatbird_spawn_line;
+				let cart_loc = at.unwrap_or_else(|| iso_to_cart(line_start.lerp(line_end, self.game_rng.random(RngStream::Spawns))));
+				self.enemy_spawner.spawn(EnemyKind::Goatbird, cart_loc, Vec2::X);
+			},
+			Spawnable::Spitter => {
+				let Some(cart_loc) = at.or_else(|| self.level.0.spitter_spawns.choose(rng).copied()) else {return};
+				self.enemy_spawner.spawn_spitter(cart_loc, entry.spitter);
+			},
+			Spawnable::Burrower => {
+				let pens: Vec<&LandShape> = self.level.0.goals.iter().map(|pen| &pen.shape).collect();
+				let Some(cart_loc) = at.or_else(|| land_point(&self.level.0.land, &pens, rng)) else {return};
+				self.enemy_spawner.spawn(EnemyKind::Burrower, cart_loc, Vec2::ZERO);
+			},
+			// Every arm the level has, taking turns as its data says
+			Spawnable::Arm => {
+				for attack in self.level.0.arms.iter() {
+					self.enemy_spawner.spawn_arm(attack);
+				}
+			},
+		}
+	}
+}
+
+// Just inside the land's outermost corner in a random direction, facing back
+// towards the middle of the map. The corner can be off the land where tiles
+// overlap or fall away, so other directions get tried when the goat's feet
+// wouldn't be on land outside the pens.
+fn land_edge(
+	land: &[LandShape],
+	ground: &Ground,
+	rng: &mut impl Rng,
+) -> Option<(Vec2, Vec2)> {
+	for _ in 0..LAND_POINT_TRIES {
+		let direction = Vec2::from_angle(rng.gen_range(0.0..TAU));
+		let (shape, corner) = land.iter()
+			.flat_map(|shape| shape.points().into_iter().map(move |corner| (shape, corner)))
+			.max_by(|(_, a), (_, b)| a.dot(direction).total_cmp(&b.dot(direction)))?;
+		let cart_loc = corner + (shape.center() - corner).clamp_length_max(rng.gen_range(STRAY_INSET..STRAY_INSET * 3.0));
+		let feet = cart_loc - iso_to_cart(Vec2::new(0.0, 32.0));
+		if ground.on_land(feet) && ground.pen_at(feet).is_none() {
+			return Some((cart_loc, (-cart_loc).try_normalize().unwrap_or(Vec2::X)));
+		}
+	}
+	None
+}
+
+// Somewhere well inside a random land tile and out of the pens
+fn land_point(
+	land: &[LandShape],
+	pens: &[&LandShape],
+	rng: &mut impl Rng,
+) -> Option<Vec2> {
+	for _ in 0..LAND_POINT_TRIES {
+		let shape = land.choose(rng)?;
+		let points = shape.points();
+		let i = rng.gen_range(0..points.len());
+		let edge = points[i].lerp(points[(i + 1) % points.len()], rng.gen());
+		let cart_loc = shape.center().lerp(edge, rng.gen::<f32>().sqrt() * 0.8);
+		if !pens.iter().any(|pen| pen.contains(cart_loc)) {
+			return Some(cart_loc);
+		}
+	}
+	None
+}
+
+#[derive(Default)]
+struct WaveScriptLoader;
+
+impl AssetLoader for WaveScriptLoader {
+	type Asset = WaveScript;
+	type Settings = ();
+	type Error = Box<dyn std::error::Error + Send + Sync>;
+
+	fn load<'a>(
+		&'a self,
+		reader: &'a mut Reader,
+		_settings: &'a (),
+		_load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<WaveScript, Self::Error>> {
+		Box::pin(async move {
+			let mut bytes = Vec::new();
+			reader.read_to_end(&mut bytes).await?;
+			let script = ron::de::from_bytes::<WaveScript>(&bytes)?;
+			if script.entries.iter().any(|entry| entry.repeat.is_some_and(|repeat| repeat < MIN_REPEAT)) {
+				return Err(format!("Wave entries can't repeat more often than every {} seconds", MIN_REPEAT).into());
+			}
+			Ok(script)
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["wave.ron"]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, SeedableRng};
+
+	use super::*;
+
+	fn spawn(
+		x: f32,
+		weight: f32,
+		from: f32,
+		until: Option<f32>,
+	) -> GoatSpawn {
+		GoatSpawn{weight, from, until, ..GoatSpawn::new(Vec2::new(x, 0.0))}
+	}
+
+	fn picks(
+		spawns: &[GoatSpawn],
+		elapsed: f32,
+	) -> Vec<f32> {
+		let mut rng = StdRng::seed_from_u64(7);
+		(0..1000).filter_map(|_| pick_goat_spawn(spawns, elapsed, &mut rng)).map(|spawn| spawn.point.x).collect()
+	}
+
+	#[test]
+	fn only_open_spawns() {
+		let spawns = [spawn(0.0, 1.0, 0.0, Some(10.0)), spawn(1.0, 1.0, 5.0, None)];
+		assert!(picks(&spawns, 2.0).iter().all(|&x| x == 0.0));
+		assert!(picks(&spawns, 7.0).contains(&0.0) && picks(&spawns, 7.0).contains(&1.0));
+		// until is when it closes, not the last moment it's open
+		assert!(picks(&spawns, 10.0).iter().all(|&x| x == 1.0));
+	}
+
+	#[test]
+	fn none_when_all_closed() {
+		let spawns = [spawn(0.0, 1.0, 5.0, None), spawn(1.0, 0.0, 0.0, None)];
+		assert!(picks(&spawns, 2.0).is_empty());
+		assert!(picks(&[], 2.0).is_empty());
+	}
+
+	#[test]
+	fn picked_by_weight() {
+		let spawns = [spawn(0.0, 3.0, 0.0, None), spawn(1.0, 1.0, 0.0, None), spawn(2.0, 0.0, 0.0, None)];
+		let picked = picks(&spawns, 0.0);
+		let heavy = picked.iter().filter(|&&x| x == 0.0).count();
+		assert_eq!(picked.len(), 1000);
+		assert!((700..800).contains(&heavy), "{} of 1000 picks", heavy);
+		assert!(!picked.contains(&2.0));
+	}
+}
@@ -0,0 +1,152 @@
+// Zone module, for patches of ground that change how goats and the player move
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{helper::{cart_to_iso, iso_to_cart, GameState}, land::LandShape, level::{CurrentLevel, LevelSelect}, terrain::{tile_centers, tile_image, TILE}};
+
+pub struct ZonePlugin;
+
+impl Plugin for ZonePlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(Startup, (
+				build_zone_images,
+			))
+			.add_systems(OnEnter(GameState::Game), (
+				zone_setup.after(LevelSelect),
+			))
+		;
+	}
+}
+
+// Over the terrain and dynamic land, under goats and the player
+const ZONE_Z: f32 = 557.0;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum ZoneKind {
+	Mud,
+	Ice,
+	TallGrass,
+	ShallowWater,
+}
+
+impl ZoneKind {
+	pub fn modifier(
+		&self,
+	) -> MovementModifier {
+		match self {
+			ZoneKind::Mud => MovementModifier{speed: 0.5, friction: 2.0, turning: 0.7},
+			ZoneKind::Ice => MovementModifier{speed: 1.2, friction: 0.15, turning: 0.3},
+			ZoneKind::TallGrass => MovementModifier{speed: 0.75, friction: 1.2, turning: 1.0},
+			ZoneKind::ShallowWater => MovementModifier{speed: 0.6, friction: 1.5, turning: 0.8},
+		}
+	}
+}
+
+// Multipliers on normal movement. Turning scales how sharply goats wander
+// and how quickly the player can change direction.
+#[derive(Clone, Copy, Debug)]
+pub struct MovementModifier{
+	pub speed: f32,
+	pub friction: f32,
+	pub turning: f32,
+}
+
+impl MovementModifier {
+	pub const NONE: MovementModifier = MovementModifier{speed: 1.0, friction: 1.0, turning: 1.0};
+
+	pub fn combine(
+		&self,
+		other: MovementModifier,
+	) -> MovementModifier {
+		MovementModifier{
+			speed: self.speed * other.speed,
+			friction: self.friction * other.friction,
+			turning: self.turning * other.turning,
+		}
+	}
+}
+
+// Also the level data for a zone, shapes are in cart space
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct Zone{
+	pub shape: LandShape,
+	pub kind: ZoneKind,
+}
+
+#[derive(Resource)]
+struct ZoneTiles{
+	mud: Handle<Image>,
+	ice: Handle<Image>,
+	tall_grass: Handle<Image>,
+	shallow_water: Handle<Image>,
+}
+
+impl ZoneTiles {
+	fn get(
+		&self,
+		kind: ZoneKind,
+	) -> &Handle<Image> {
+		match kind {
+			ZoneKind::Mud => &self.mud,
+			ZoneKind::Ice => &self.ice,
+			ZoneKind::TallGrass => &self.tall_grass,
+			ZoneKind::ShallowWater => &self.shallow_water,
+		}
+	}
+}
+
+fn build_zone_images(
+	mut commands: Commands,
+	mut images: ResMut<Assets<Image>>,
+) {
+	let size = Vec2::new(TILE * 2.0, TILE);
+	let mut zone_image = |pattern: fn(Vec2) -> Color| {
+		images.add(tile_image(size, |iso| {
+			let cart = iso_to_cart(iso);
+			let half = TILE/2.0 + 0.5;
+			(cart.x.abs() <= half && cart.y.abs() <= half).then(|| pattern(iso))
+		}))
+	};
+	commands.insert_resource(ZoneTiles{
+		mud: zone_image(|iso| {
+			let blob = ((iso.x * 0.7).sin() + (iso.y * 1.3).cos()) * 0.04;
+			Color::rgba(0.36 + blob, 0.26 + blob, 0.16, 0.9)
+		}),
+		ice: zone_image(|iso| {
+			let streak = (iso.x + iso.y * 2.0).rem_euclid(12.0) < 1.5;
+			if streak {Color::rgba(0.95, 0.98, 1.0, 0.85)} else {Color::rgba(0.72, 0.86, 0.95, 0.75)}
+		}),
+		tall_grass: zone_image(|iso| {
+			let blade = (iso.x.floor() as i32).rem_euclid(3) == 0 && (iso.x * 3.7 + iso.y).sin() > 0.0;
+			if blade {Color::rgba(0.2, 0.4, 0.14, 0.95)} else {Color::rgba(0.3, 0.52, 0.2, 0.85)}
+		}),
+		shallow_water: zone_image(|iso| {
+			let ripple = (iso.y * 0.9 + (iso.x * 0.3).sin() * 2.0).rem_euclid(6.0) < 1.0;
+			if ripple {Color::rgba(0.75, 0.88, 0.95, 0.7)} else {Color::rgba(0.3, 0.55, 0.78, 0.6)}
+		}),
+	});
+}
+
+fn zone_setup(
+	mut commands: Commands,
+	zone_tiles: Res<ZoneTiles>,
+	level: Res<CurrentLevel>,
+) {
+	for zone in level.0.zones.iter() {
+		commands
+			.spawn((SpatialBundle::default(),
+				zone.clone(),
+			)).with_children(|parent| {
+				for center in tile_centers(&zone.shape) {
+					let iso_loc = cart_to_iso(center);
+					parent.spawn((SpriteBundle {
+						transform: Transform::from_xyz(iso_loc.x, iso_loc.y, ZONE_Z),
+						texture: zone_tiles.get(zone.kind).clone(),
+						..default()
+						},
+					));
+				}
+			});
+	}
+}